@@ -0,0 +1,124 @@
+/*
+Modulo de errores: todas las respuestas de error de la API usan el mismo cuerpo JSON,
+{"code": <status http>, "message": "..."}, para que los clientes puedan leerlas siempre igual.
+*/
+use salvo::http::{ParseError, StatusCode};
+use salvo::oapi::ToSchema;
+use serde::Serialize;
+
+#[derive(Serialize, ToSchema, Debug)]
+pub struct ApiError {
+    pub code: u16,
+    pub message: String,
+    // ids involucrados en el error, por ejemplo los que chocan en un batch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<i64>>,
+    // por que no se pudo leer el cuerpo, por ejemplo "missing field `text` at line 1 column 2"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    // path pedido, en el 404 de una ruta que no existe
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+// largo maximo del detail, un cuerpo enorme no se devuelve entero en el mensaje de serde
+const MAX_DETAIL_LEN: usize = 200;
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            code: status.as_u16(),
+            message: message.into(),
+            ids: None,
+            detail: None,
+            path: None,
+        }
+    }
+
+    pub fn not_found(id: i64) -> Self {
+        Self::new(StatusCode::NOT_FOUND, format!("todo {id} not found"))
+    }
+
+    pub fn route_not_found(path: &str) -> Self {
+        ApiError {
+            path: Some(path.to_string()),
+            ..Self::new(StatusCode::NOT_FOUND, "not found")
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    /*
+    400 para un cuerpo que no se pudo parsear. Solo se cuenta el detalle de los errores de serde, que hablan
+    del cuerpo que mando el cliente; los de I/O o de hyper son del servidor y van sin detalle.
+    */
+    pub fn invalid_body(error: &ParseError) -> Self {
+        let detail = match error {
+            ParseError::SerdeJson(e) => Some(e.to_string()),
+            ParseError::Deserialize(e) => Some(e.to_string()),
+            ParseError::EmptyBody => Some("the request body is empty".to_string()),
+            _ => None,
+        };
+        ApiError {
+            detail: detail.map(|detail| detail.chars().take(MAX_DETAIL_LEN).collect()),
+            ..Self::bad_request("invalid request body")
+        }
+    }
+
+    pub fn precondition_failed(id: i64) -> Self {
+        Self::new(
+            StatusCode::PRECONDITION_FAILED,
+            format!("todo {id} does not match If-Match"),
+        )
+    }
+
+    pub fn duplicate_text(existing_id: i64) -> Self {
+        ApiError {
+            ids: Some(vec![existing_id]),
+            ..Self::new(
+                StatusCode::CONFLICT,
+                format!("todo {existing_id} already has the same text"),
+            )
+        }
+    }
+
+    pub fn payload_too_large(max: u64) -> Self {
+        Self::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("request body must be at most {max} bytes"),
+        )
+    }
+
+    pub fn store_full(max: usize) -> Self {
+        Self::new(
+            StatusCode::INSUFFICIENT_STORAGE,
+            format!("the store is full, it holds at most {max} todos"),
+        )
+    }
+
+    pub fn too_many_tenants(max: usize) -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("{max} users are already active, try again later"),
+        )
+    }
+
+    // el detalle (que archivo, que error) queda en el log, no en la respuesta
+    pub fn store_unavailable() -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "the todos could not be loaded")
+    }
+
+    // el status con el que se responde este error
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from_u16(self.code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    pub fn conflicting_ids(ids: Vec<i64>) -> Self {
+        ApiError {
+            ids: Some(ids),
+            ..Self::new(StatusCode::CONFLICT, "conflicting ids")
+        }
+    }
+}
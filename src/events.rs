@@ -0,0 +1,101 @@
+/*
+Eventos de cambios en el store: los handlers que modifican todos publican un TodoEvent
+en un canal broadcast y cada cliente conectado a /todos/events (WebSocket) o /todos/stream (SSE)
+recibe su propia copia. En modo multi-tenant cada cliente solo recibe los eventos de su usuario.
+*/
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use salvo::oapi::ToSchema;
+use serde::Serialize;
+use tokio::sync::{broadcast, watch};
+
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::models::Todo;
+use crate::tenant;
+
+// cuantos eventos se guardan para un cliente lento antes de que empiece a perderlos
+pub const EVENTS_CAPACITY: usize = 64;
+
+// cada cuanto se manda algo por los streams de eventos aunque no haya cambios
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+// cada evento viaja con el usuario que hizo el cambio (None fuera del modo multi-tenant)
+static EVENTS: Lazy<broadcast::Sender<(Option<String>, TodoEvent)>> =
+    Lazy::new(|| broadcast::channel(EVENTS_CAPACITY).0);
+
+// pasa a true cuando el servidor se esta apagando, para cortar las conexiones que quedan abiertas
+static SHUTDOWN: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl EventKind {
+    // el mismo nombre que en el JSON, lo usa SSE como nombre del evento
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Updated => "updated",
+            EventKind::Deleted => "deleted",
+        }
+    }
+}
+
+// {"type": "created", "todo": {...}}
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct TodoEvent {
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    pub todo: Todo,
+}
+
+// manda el evento a todos los suscriptores; si no hay ninguno conectado no pasa nada
+pub fn publish(kind: EventKind, todo: &Todo) {
+    let event = TodoEvent {
+        kind,
+        todo: todo.clone(),
+    };
+    let _ = EVENTS.send((tenant::current(), event));
+}
+
+// los eventos que le llegan a un cliente, solo los del usuario que se suscribio
+pub struct Subscription {
+    receiver: broadcast::Receiver<(Option<String>, TodoEvent)>,
+    user: Option<String>,
+}
+
+impl Subscription {
+    // como broadcast::Receiver::recv, salteando los eventos de otros usuarios
+    pub async fn recv(&mut self) -> Result<TodoEvent, RecvError> {
+        loop {
+            let (user, event) = self.receiver.recv().await?;
+            if user == self.user {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+pub fn subscribe() -> Subscription {
+    Subscription {
+        receiver: EVENTS.subscribe(),
+        user: tenant::current(),
+    }
+}
+
+// avisa a los suscriptores que el servidor se apaga para que terminen sus streams
+pub fn shutdown() {
+    SHUTDOWN.send_replace(true);
+}
+
+// termina cuando se llamo a shutdown() (enseguida si ya se habia llamado)
+pub async fn closed() {
+    let mut shutdown = SHUTDOWN.subscribe();
+    let _ = shutdown.wait_for(|closed| *closed).await;
+}
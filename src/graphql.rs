@@ -0,0 +1,221 @@
+/*
+API GraphQL en POST /graphql, al lado de la REST y sobre el mismo STORE. Las mutaciones siguen las mismas
+reglas que los endpoints REST (normalize, validate, parent, version) y publican los mismos eventos.
+En GET /graphql/playground queda GraphiQL para probar queries desde el navegador.
+*/
+use std::sync::atomic::Ordering;
+
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{EmptySubscription, Error, ErrorExtensions, InputObject, Object, Result, Schema};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use salvo::prelude::*;
+
+use crate::errors::ApiError;
+use crate::events::{self, EventKind};
+use crate::models::{ListOptions, NewTodo, PatchBody, Todo, TodoPatch};
+use crate::store::StoreError;
+use crate::{live_todo, next_position, patched_todo, remove_with_children, require_json, snapshot, store};
+use crate::{check_capacity, check_parent, create_next_occurrence};
+use crate::{case_sensitive_search, COLLAPSE_WHITESPACE, CREATE_LOCK, DEFAULT_LIMIT, MAX_PAGE_LIMIT, NEXT_ID};
+
+pub type TodoSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+static SCHEMA: Lazy<TodoSchema> = Lazy::new(|| Schema::new(QueryRoot, MutationRoot, EmptySubscription));
+
+// los mismos filtros y orden que los query params de GET /todos
+#[derive(InputObject, Default)]
+pub struct TodoFilter {
+    pub completed: Option<bool>,
+    pub q: Option<String>,
+    pub tag: Option<String>,
+    pub min_priority: Option<u8>,
+    pub overdue: Option<bool>,
+    pub updated_since: Option<DateTime<Utc>>,
+    pub assignee: Option<String>,
+    pub unassigned: Option<bool>,
+    pub sort_by: Option<String>,
+    pub order: Option<String>,
+}
+
+// limit tiene el mismo default DEFAULT_LIMIT y el mismo tope MAX_PAGE_LIMIT que en REST
+#[derive(InputObject, Default)]
+pub struct Pagination {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(InputObject)]
+pub struct CreateTodoInput {
+    pub text: String,
+    pub completed: Option<bool>,
+    pub priority: Option<u8>,
+    pub tags: Option<Vec<String>>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub parent_id: Option<i64>,
+    pub recurrence: Option<String>,
+    pub assignee: Option<String>,
+}
+
+// como TodoPatch: los campos que no vienen quedan como estaban
+#[derive(InputObject)]
+pub struct UpdateTodoInput {
+    pub text: Option<String>,
+    pub completed: Option<bool>,
+    pub priority: Option<u8>,
+    pub tags: Option<Vec<String>>,
+    pub due_date: Option<DateTime<Utc>>,
+    pub parent_id: Option<i64>,
+    pub recurrence: Option<String>,
+    pub assignee: Option<String>,
+}
+
+// los errores llevan en extensions.code el mismo status que devolveria la API REST
+fn api_error(status: StatusCode, message: impl Into<String>) -> Error {
+    Error::new(message).extend_with(|_, extensions| extensions.set("code", status.as_u16()))
+}
+
+fn store_error(error: StoreError) -> Error {
+    let status = match error {
+        StoreError::NotFound(_) => StatusCode::NOT_FOUND,
+        StoreError::IdTaken(_) | StoreError::Conflict(_) => StatusCode::CONFLICT,
+    };
+    api_error(status, error.to_string())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn todos(&self, filter: Option<TodoFilter>, pagination: Option<Pagination>) -> Result<Vec<Todo>> {
+        let filter = filter.unwrap_or_default();
+        let pagination = pagination.unwrap_or_default();
+        let opts = ListOptions {
+            completed: filter.completed,
+            q: filter.q,
+            tag: filter.tag,
+            min_priority: filter.min_priority,
+            overdue: filter.overdue,
+            updated_since: filter.updated_since,
+            assignee: filter.assignee,
+            unassigned: filter.unassigned,
+            sort_by: filter.sort_by,
+            order: filter.order,
+            ..Default::default()
+        };
+        let mut todos = store().list().await;
+        todos.retain(|todo| opts.matches(todo, case_sensitive_search()));
+        opts.sort(&mut todos)
+            .map_err(|message| api_error(StatusCode::BAD_REQUEST, message))?;
+        let limit = pagination.limit.unwrap_or(*DEFAULT_LIMIT).min(*MAX_PAGE_LIMIT);
+        Ok(todos
+            .into_iter()
+            .skip(pagination.offset.unwrap_or(0))
+            .take(limit)
+            .collect())
+    }
+
+    async fn todo(&self, id: i64) -> Option<Todo> {
+        store().get(id).await.filter(|todo| !todo.is_deleted())
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn create_todo(&self, input: CreateTodoInput) -> Result<Todo> {
+        let mut todo = NewTodo {
+            text: input.text,
+            completed: input.completed.unwrap_or(false),
+            priority: input.priority,
+            tags: input.tags.unwrap_or_default(),
+            due_date: input.due_date,
+            parent_id: input.parent_id,
+            recurrence: input.recurrence,
+            assignee: input.assignee,
+        }
+        .into_todo(NEXT_ID.load(Ordering::Relaxed));
+        todo.normalize(*COLLAPSE_WHITESPACE);
+        todo.validate()
+            .map_err(|e| api_error(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+        let _create = CREATE_LOCK.lock().await;
+        let todos = snapshot().await;
+        check_capacity(todos.len(), 1).map_err(|e| api_error(StatusCode::INSUFFICIENT_STORAGE, e.message))?;
+        check_parent(&todos, todo.id, todo.parent_id)
+            .map_err(|message| api_error(StatusCode::BAD_REQUEST, message))?;
+        todo.position = next_position(&todos);
+        // como en POST /todos, el id se toma recien cuando pasaron los chequeos
+        todo.id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let todo = store().create(todo).await.map_err(store_error)?;
+        events::publish(EventKind::Created, &todo);
+        Ok(todo)
+    }
+
+    // igual que PATCH /todos/<id>: version es la que leyo el cliente. Si completa un todo recurrente se crea la
+    // siguiente ocurrencia, que llega por los eventos (o por la query todos) pero no se devuelve aca
+    async fn update_todo(&self, id: i64, version: u64, input: UpdateTodoInput) -> Result<Todo> {
+        let patch = TodoPatch {
+            version: Some(version),
+            text: input.text,
+            completed: input.completed,
+            priority: input.priority,
+            tags: input.tags,
+            due_date: input.due_date,
+            parent_id: input.parent_id,
+            recurrence: input.recurrence,
+            assignee: input.assignee,
+        };
+        let todos = snapshot().await;
+        let stored = live_todo(&todos, id)
+            .ok_or_else(|| api_error(StatusCode::NOT_FOUND, ApiError::not_found(id).message))?;
+        let mut todo = patched_todo(&todos, stored, PatchBody::Fields(patch))
+            .map_err(|(status, message)| api_error(status, message))?;
+        let now = Utc::now();
+        todo.track_completion(stored.completed, now);
+        let recurrence = todo.end_recurrence(stored.completed);
+        todo.version = version;
+        todo.updated_at = now;
+        let todo = store().update(todo).await.map_err(store_error)?;
+        events::publish(EventKind::Updated, &todo);
+        if let Some(recurrence) = recurrence {
+            create_next_occurrence(&todo, recurrence).await;
+        }
+        Ok(todo)
+    }
+
+    // como el DELETE por defecto: borrado logico y los hijos pasan al padre. false si no existia
+    async fn delete_todo(&self, id: i64) -> bool {
+        let removal = store()
+            .transaction(|todos| remove_with_children(todos, id, false, false))
+            .await;
+        let Some(removal) = removal else {
+            return false;
+        };
+        removal.publish();
+        true
+    }
+}
+
+#[handler]
+pub async fn graphql(req: &mut Request, res: &mut Response) {
+    if !require_json(req, res) {
+        return;
+    }
+    let request = match req.parse_json::<async_graphql::Request>().await {
+        Ok(request) => request,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid graphql request");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    res.render(Json(SCHEMA.execute(request).await));
+}
+
+#[handler]
+pub async fn playground(res: &mut Response) {
+    res.render(Text::Html(GraphiQLSource::build().endpoint("/graphql").finish()));
+}
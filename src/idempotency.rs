@@ -0,0 +1,98 @@
+/*
+Soporte para el header Idempotency-Key de create_todo: guardamos el todo que se creo con cada key
+y si la misma key vuelve a llegar (por ejemplo un reintento despues de un timeout) devolvemos
+ese mismo todo en vez de crear otro. Las keys vencen despues de `ttl`, y si hay mas de `max_keys` sin vencer
+se olvidan las mas viejas, asi un cliente que manda una key nueva por request no llena la memoria.
+El mismo cache sirve para la ventana de CREATE_DEDUP_WINDOW_SECS, con insert_for y la ventana como ttl.
+*/
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::Todo;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+// cuanto tiempo se recuerda una key si no se define IDEMPOTENCY_TTL_SECS (24 horas)
+pub const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// cuantas keys se recuerdan como mucho si no se define IDEMPOTENCY_MAX_KEYS
+pub const DEFAULT_MAX_KEYS: usize = 10_000;
+
+struct Entry {
+    todo: Todo,
+    created: Instant,
+    ttl: Duration,
+}
+
+pub struct IdempotencyCache {
+    ttl: Duration,
+    max_keys: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration) -> Self {
+        IdempotencyCache {
+            ttl,
+            max_keys: DEFAULT_MAX_KEYS,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // el mismo cache, pero recordando como mucho max_keys (al menos una)
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys.max(1);
+        self
+    }
+
+    /*
+    Lee el ttl en segundos de IDEMPOTENCY_TTL_SECS, por defecto DEFAULT_IDEMPOTENCY_TTL,
+    y el tope de keys de IDEMPOTENCY_MAX_KEYS, por defecto DEFAULT_MAX_KEYS.
+    */
+    pub fn from_env() -> Self {
+        let ttl = crate::env_parse("IDEMPOTENCY_TTL_SECS").map_or(DEFAULT_IDEMPOTENCY_TTL, Duration::from_secs);
+        let max_keys = crate::env_parse("IDEMPOTENCY_MAX_KEYS").unwrap_or(DEFAULT_MAX_KEYS);
+        Self::new(ttl).with_max_keys(max_keys)
+    }
+
+    // el todo creado con esta key, si todavia no vencio
+    pub fn get(&self, key: &str) -> Option<Todo> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .filter(|entry| entry.created.elapsed() < entry.ttl)
+            .map(|entry| entry.todo.clone())
+    }
+
+    /*
+    Recuerda el todo creado con la key. De paso saca las keys vencidas y, si igual no hay lugar,
+    la mas vieja, asi el mapa no pasa de max_keys.
+    */
+    pub fn insert(&self, key: String, todo: Todo) {
+        self.insert_for(key, todo, self.ttl);
+    }
+
+    // como insert, pero esta key vence despues de ttl en vez del ttl del cache
+    pub fn insert_for(&self, key: String, todo: Todo, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, entry| entry.created.elapsed() < entry.ttl);
+        if !entries.contains_key(&key) && entries.len() >= self.max_keys {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.created)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                todo,
+                created: Instant::now(),
+                ttl,
+            },
+        );
+    }
+}
@@ -0,0 +1,3854 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use hyper_util::rt::TokioTimer;
+use once_cell::sync::Lazy;
+
+use salvo::prelude::*;
+use salvo::catcher::Catcher;
+use salvo::cors::{AllowOrigin, Cors, CorsHandler};
+use salvo::http::header::{self, HeaderValue};
+use salvo::http::Method;
+use salvo::oapi::swagger_ui::SwaggerUi;
+use salvo::server::ServerHandle;
+use salvo::sse::{SseEvent, SseKeepAlive};
+use salvo::websocket::{Message, WebSocketUpgrade};
+use tokio::sync::broadcast::error::RecvError;
+use serde_json::json;
+
+// use self::models::*;: Importa todos los elementos 
+//(estructuras, funciones, etc.) desde el módulo models del mismo archivo.
+use self::models::*;
+use self::errors::ApiError;
+use self::events::EventKind;
+use self::idempotency::{IdempotencyCache, IDEMPOTENCY_KEY_HEADER};
+use self::middleware::{ApiKeyAuth, BodySizeLimit, ContentLengthLimit, MethodNotAllowed, RateLimiter, SecurityHeaders};
+use self::sqlite::SqliteStore;
+use self::store::{MemoryStore, StoreError, TodoStore};
+
+pub mod errors;
+pub mod events;
+pub mod graphql;
+pub mod idempotency;
+pub mod metrics;
+pub mod middleware;
+pub mod persistence;
+pub mod sqlite;
+pub mod store;
+pub mod tenant;
+pub mod webhook;
+
+// la base SQLite de DATABASE_URL, ver el modulo sqlite; sin la variable los todos van al archivo JSON de siempre
+static DATABASE: Lazy<Option<std::path::PathBuf>> = Lazy::new(|| {
+    let url = std::env::var("DATABASE_URL").ok()?;
+    Some(sqlite::database_path(&url).unwrap_or_else(|e| panic!("{e}")))
+});
+
+/*
+establece una variable estática llamada STORE 
+que contiene un Lazy inicializado con el store (la base de DATABASE_URL, o el MemoryStore con los todos guardados
+en disco, o vacío si todavía no hay archivo).
+La utilización de Lazy asegura que la inicialización del almacenamiento se realice de manera diferida, es decir, 
+solo cuando sea necesario, evitando así la inicialización innecesaria
+*/
+static STORE: Lazy<Arc<dyn TodoStore>> = Lazy::new(|| match &*DATABASE {
+    Some(database) => Arc::new(SqliteStore::open(database).unwrap_or_else(|e| panic!("{e}"))),
+    None => Arc::new(MemoryStore::load()),
+});
+
+// cuantos stores de usuarios se tienen cargados a la vez si no se define MAX_TENANT_STORES
+const DEFAULT_MAX_TENANT_STORES: usize = 256;
+
+static MAX_TENANT_STORES: Lazy<usize> =
+    Lazy::new(|| env_parse("MAX_TENANT_STORES").unwrap_or(DEFAULT_MAX_TENANT_STORES));
+
+struct TenantStore {
+    store: Arc<dyn TodoStore>,
+    last_used: Instant,
+}
+
+/*
+En modo multi-tenant cada usuario tiene su store, que se carga de su archivo (o se abre su base) la primera vez que
+se usa. Hay a lo sumo MAX_TENANT_STORES cargados: para hacer lugar se descarga el usado hace mas tiempo, que ya esta
+entero en disco porque cada cambio se guarda al momento. Los ids siguen siendo unicos entre todos los usuarios.
+*/
+static TENANT_STORES: Lazy<std::sync::Mutex<HashMap<String, TenantStore>>> = Lazy::new(Default::default);
+
+/*
+El store de user para una request suya, lo llama middleware::tenant_scope. Si user no tiene archivo y la request
+solo lee, devolvemos un store vacio que no se guarda en TENANT_STORES: asi un cliente que prueba ids de usuario al
+azar no llena la memoria. Si los MAX_TENANT_STORES cargados tienen requests en curso, ninguno se puede descargar
+sin perder lo que esas requests escriban y respondemos 503. Si su archivo no se puede leer, 500.
+*/
+pub(crate) fn tenant_store(user: &str, read_only: bool) -> Result<Arc<dyn TodoStore>, ApiError> {
+    let mut stores = TENANT_STORES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(tenant) = stores.get_mut(user) {
+        tenant.last_used = Instant::now();
+        return Ok(tenant.store.clone());
+    }
+    let file = match &*DATABASE {
+        Some(database) => sqlite::tenant_path(database, user),
+        None => persistence::tenant_file(user),
+    };
+    if read_only && !file.exists() {
+        return Ok(Arc::new(MemoryStore::new(HashMap::new())));
+    }
+    // no guardamos nada en TENANT_STORES: la proxima request vuelve a probar, por si alguien arreglo el archivo
+    let unavailable = |error: String| {
+        tracing::error!(%user, %error, "failed to load tenant store");
+        ApiError::store_unavailable()
+    };
+    let (store, max_id): (Arc<dyn TodoStore>, Option<i64>) = if DATABASE.is_some() {
+        let store = SqliteStore::open(&file).map_err(unavailable)?;
+        let max_id = store.max_id();
+        (Arc::new(store), max_id)
+    } else {
+        let todos = persistence::load_from(&file).map_err(unavailable)?;
+        let max_id = todos.keys().max().copied();
+        (Arc::new(MemoryStore::new(todos).with_cache(store::cache_capacity())), max_id)
+    };
+    if let Some(max_id) = max_id {
+        NEXT_ID.fetch_max(max_id + 1, Ordering::Relaxed);
+    }
+    if stores.len() >= *MAX_TENANT_STORES {
+        // con el lock del mapa tomado, un store que solo esta en el mapa no lo puede estar usando nadie
+        let idle = stores
+            .iter()
+            .filter(|(_, tenant)| Arc::strong_count(&tenant.store) == 1)
+            .min_by_key(|(_, tenant)| tenant.last_used)
+            .map(|(user, _)| user.clone());
+        let Some(idle) = idle else {
+            return Err(ApiError::too_many_tenants(*MAX_TENANT_STORES));
+        };
+        tracing::debug!(user = %idle, "unloading tenant store");
+        stores.remove(&idle);
+    }
+    let tenant = TenantStore {
+        store: store.clone(),
+        last_used: Instant::now(),
+    };
+    stores.insert(user.to_string(), tenant);
+    Ok(store)
+}
+
+// el store de la request en curso: el del usuario en modo multi-tenant, si no STORE. Los handlers lo usan a traves
+// del trait, ver el modulo store
+fn store() -> Arc<dyn TodoStore> {
+    tenant::current_store().unwrap_or_else(|| STORE.clone())
+}
+
+// direccion donde escucha el servidor si no se define BIND_ADDR
+const DEFAULT_BIND_ADDR: &str = "127.0.0.1:8080";
+
+// tiempo maximo que esperamos a que terminen las requests en curso al apagar el servidor
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*
+Timeouts de conexion, para que un cliente lento (slow-loris) no retenga conexiones para siempre.
+HEADER_READ_TIMEOUT_SECS: cuanto puede tardar el cliente en mandar los headers de la request.
+IDLE_TIMEOUT_SECS: una conexion sin trafico en ninguna direccion se cierra, tambien cubre
+a los clientes que dejan de leer la respuesta.
+*/
+const DEFAULT_HEADER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// cada cuanto watch_overdue busca todos vencidos para el webhook si no se define WEBHOOK_INTERVAL_SECS
+const DEFAULT_WEBHOOK_INTERVAL: Duration = Duration::from_secs(60);
+
+// cada cuanto log_store_stats escribe el resumen del store si no se define STATS_LOG_INTERVAL_SECS (0 lo apaga)
+const DEFAULT_STATS_LOG_INTERVAL: Duration = Duration::from_secs(300);
+
+// content type de JSON Merge Patch (RFC 7386) que acepta patch_todo
+const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+// content type de PATCH /todos/<id> con una lista de operaciones de JSON Patch (RFC 6902)
+const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+// content type de la salida en formato JSON por lineas de list_todos
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+// limite de pagina de list_todos si no se define MAX_PAGE_LIMIT
+const DEFAULT_MAX_PAGE_LIMIT: usize = 100;
+
+/*
+Tamaño maximo de una pagina de list_todos. Un limit mas grande se recorta a este valor
+y la respuesta lo avisa con el header X-Limit-Clamped, asi nadie puede clonar el store entero de una vez.
+*/
+static MAX_PAGE_LIMIT: Lazy<usize> = Lazy::new(|| env_parse("MAX_PAGE_LIMIT").unwrap_or(DEFAULT_MAX_PAGE_LIMIT));
+
+// limit de list_todos cuando el cliente no manda uno, si no se define DEFAULT_LIMIT
+const DEFAULT_PAGE_LIMIT: usize = 50;
+
+/*
+Limit que usa list_todos (y la query todos de GraphQL) si el cliente no manda uno, de DEFAULT_LIMIT.
+Nunca pasa de MAX_PAGE_LIMIT. El limit que se uso vuelve en el header X-Limit, asi el cliente sabe que hay paginas.
+*/
+static DEFAULT_LIMIT: Lazy<usize> =
+    Lazy::new(|| env_parse("DEFAULT_LIMIT").unwrap_or(DEFAULT_PAGE_LIMIT).min(*MAX_PAGE_LIMIT));
+
+/*
+Con MAX_TODOS=<n> el store guarda a lo sumo n todos (los borrados logicamente tambien cuentan, siguen ocupando
+memoria) y las creaciones que lo pasarian responden 507. Sin la variable no hay tope. Se lee en cada request,
+como DEV_MODE, asi los tests la pueden cambiar. Un valor invalido no deja arrancar (ver check_config); si igual
+aparece despues se avisa en el log y no pone tope.
+*/
+fn max_todos() -> Option<usize> {
+    let value = std::env::var("MAX_TODOS").ok()?;
+    match value.parse() {
+        Ok(max) => Some(max),
+        Err(e) => {
+            tracing::warn!(%value, error = %e, "invalid MAX_TODOS, not limiting the store");
+            None
+        }
+    }
+}
+
+/*
+Con CREATE_DEDUP_WINDOW_SECS=<n>, un POST /todos con el mismo cuerpo (despues de normalize) que otro creado hace
+menos de n segundos no crea nada y devuelve el todo del primero, como un Idempotency-Key pero sin que el cliente
+mande nada: frena el doble click de una UI. Sin la variable, o con 0, esta apagado. Se lee en cada request,
+como MAX_TODOS; un valor invalido no deja arrancar y, si aparece despues, se avisa en el log y deja la ventana
+apagada.
+*/
+fn create_dedup_window() -> Option<Duration> {
+    let value = std::env::var("CREATE_DEDUP_WINDOW_SECS").ok()?;
+    match value.parse() {
+        Ok(secs) => Some(Duration::from_secs(secs)).filter(|window| !window.is_zero()),
+        Err(e) => {
+            tracing::warn!(%value, error = %e, "invalid CREATE_DEDUP_WINDOW_SECS, not deduplicating creates");
+            None
+        }
+    }
+}
+
+/*
+Clave de RECENT_CREATES: los campos que manda el cliente, ya normalizados, en JSON, por usuario en modo
+multi-tenant. Es el cuerpo entero y no un hash, asi dos cuerpos distintos nunca se confunden; las claves viven
+solo lo que dura la ventana.
+*/
+fn create_dedup_key(todo: &Todo) -> String {
+    let body = json!([
+        todo.text, todo.completed, todo.priority, todo.tags, todo.due_date, todo.parent_id, todo.recurrence,
+        todo.assignee,
+    ])
+    .to_string();
+    match tenant::current() {
+        Some(user) => format!("{user}/{body}"),
+        None => body,
+    }
+}
+
+// Err con el 507 si agregar adding todos a un store que ya tiene stored pasaria MAX_TODOS
+fn check_capacity(stored: usize, adding: usize) -> Result<(), ApiError> {
+    match max_todos() {
+        Some(max) if stored + adding > max => Err(ApiError::store_full(max)),
+        _ => Ok(()),
+    }
+}
+
+// cuantos resultados devuelve como mucho GET /todos/search si no se define SEARCH_MAX_RESULTS
+const DEFAULT_SEARCH_MAX_RESULTS: usize = 20;
+
+// tope de resultados de GET /todos/search; un ?limit mas chico devuelve menos
+static SEARCH_MAX_RESULTS: Lazy<usize> =
+    Lazy::new(|| env_parse("SEARCH_MAX_RESULTS").unwrap_or(DEFAULT_SEARCH_MAX_RESULTS));
+
+/*
+Con COLLAPSE_WHITESPACE=true el text de los todos se guarda con los espacios internos repetidos reducidos
+a uno ("buy   milk" queda "buy milk"). Los espacios alrededor se sacan siempre, ver Todo::normalize.
+*/
+static COLLAPSE_WHITESPACE: Lazy<bool> =
+    Lazy::new(|| std::env::var("COLLAPSE_WHITESPACE").is_ok_and(|value| value == "true"));
+
+/*
+Con CASE_SENSITIVE_SEARCH=true el filtro ?q= de GET /todos, GET /todos/search y el ?dedupe=true de POST /todos
+distinguen mayusculas ("Milk" no encuentra "buy milk"). Por defecto no las distinguen. Los tags se guardan
+siempre en minusculas, asi que el filtro ?tag= no cambia. Se lee en cada request, como MAX_TODOS.
+*/
+fn case_sensitive_search() -> bool {
+    std::env::var("CASE_SENSITIVE_SEARCH").is_ok_and(|value| value == "true")
+}
+
+// todos creados por Idempotency-Key, para que un POST reintentado no cree un duplicado
+static IDEMPOTENCY: Lazy<IdempotencyCache> = Lazy::new(IdempotencyCache::from_env);
+
+// todos creados sin Idempotency-Key en la ultima CREATE_DEDUP_WINDOW_SECS; cada uno vence con la ventana que
+// habia al crearlo
+static RECENT_CREATES: Lazy<IdempotencyCache> = Lazy::new(|| IdempotencyCache::new(Duration::ZERO));
+
+// cuando arranco el proceso, para el uptime_secs de /health; start_server lo fija al arrancar
+static STARTED_AT: Lazy<Instant> = Lazy::new(Instant::now);
+
+// contador con el proximo id a asignar en create_todo
+static NEXT_ID: AtomicI64 = AtomicI64::new(1);
+
+/*
+Las creaciones van de a una: asi dos POST con la misma Idempotency-Key, o dos textos iguales con dedupe,
+no pasan los chequeos a la vez, y dos todos nuevos no se llevan la misma posicion.
+*/
+static CREATE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+
+/*
+Lee y valida de una vez la configuracion que si no se leeria recien con la primera request que la usa.
+La politica es siempre la misma: una variable definida con un valor que no se puede parsear aborta el arranque
+con su nombre, antes de abrir el puerto, en vez de tirar esa request o ignorarse en silencio.
+*/
+pub fn check_config() {
+    Lazy::force(&DATABASE);
+    Lazy::force(&MAX_TENANT_STORES);
+    Lazy::force(&MAX_PAGE_LIMIT);
+    Lazy::force(&DEFAULT_LIMIT);
+    Lazy::force(&SEARCH_MAX_RESULTS);
+    Lazy::force(&IDEMPOTENCY);
+    store::cache_capacity();
+    // estas dos se siguen leyendo en cada request, aca solo se validan
+    env_parse::<usize>("MAX_TODOS");
+    env_parse::<u64>("CREATE_DEDUP_WINDOW_SECS");
+}
+
+pub async fn start_server() {
+    Lazy::force(&STARTED_AT);
+    check_config();
+    // forzamos la carga del store desde disco y seguimos la numeracion de ids desde el mayor guardado
+    let max_id = STORE.list().await.iter().map(|todo| todo.id).max().unwrap_or(0);
+    NEXT_ID.store(max_id + 1, Ordering::Relaxed);
+    // el Service y el webhook leen su configuracion al armarse, tambien antes de abrir el puerto
+    let service = service();
+    let webhook = webhook::OverdueWebhook::from_env();
+    let webhook_interval = env_secs("WEBHOOK_INTERVAL_SECS", DEFAULT_WEBHOOK_INTERVAL);
+    let stats_interval = env_secs("STATS_LOG_INTERVAL_SECS", DEFAULT_STATS_LOG_INTERVAL);
+
+    // la direccion se puede cambiar con BIND_ADDR, por ejemplo BIND_ADDR=0.0.0.0:3000
+    let addr = std::env::var("BIND_ADDR").unwrap_or_else(|_| DEFAULT_BIND_ADDR.to_string());
+    let addr: SocketAddr = addr
+        .parse()
+        .unwrap_or_else(|e| panic!("invalid BIND_ADDR {addr:?}: {e}"));
+    tracing::info!(%addr, "binding server");
+
+    let header_read_timeout = env_secs("HEADER_READ_TIMEOUT_SECS", DEFAULT_HEADER_READ_TIMEOUT);
+    let idle_timeout = env_secs("IDLE_TIMEOUT_SECS", DEFAULT_IDLE_TIMEOUT);
+    tracing::info!(
+        header_read_timeout_secs = header_read_timeout.as_secs(),
+        idle_timeout_secs = idle_timeout.as_secs(),
+        "connection timeouts"
+    );
+
+    let acceptor = TcpListener::new(addr).bind().await;
+    let mut server = Server::new(acceptor).conn_idle_timeout(idle_timeout);
+    // hyper necesita un timer para poder medir el header_read_timeout
+    server
+        .http1_mut()
+        .timer(TokioTimer::new())
+        .header_read_timeout(header_read_timeout);
+    // con el handle podemos pedirle al servidor que se detenga desde otra tarea
+    tokio::spawn(shutdown_signal(server.handle()));
+    if stats_interval.is_zero() {
+        tracing::info!("store stats logging disabled");
+    } else {
+        tokio::spawn(log_store_stats(stats_interval));
+    }
+    if let Some(webhook) = webhook {
+        tracing::info!(interval_secs = webhook_interval.as_secs(), "overdue webhook enabled");
+        tokio::spawn(watch_overdue(webhook, webhook_interval.max(Duration::from_secs(1))));
+    }
+    server.serve(service).await;
+
+    // serve termina cuando se drenaron las requests en curso, ahi bajamos el store a disco
+    tracing::info!("server stopped, flushing store");
+    STORE.flush().await;
+}
+
+/*
+Un latido en el log para quien no junta /metrics: cada interval loguea cuantos todos hay (sin los borrados)
+y que parte esta completa, sobre una copia de los todos de STORE. En modo multi-tenant cuenta
+solo el store compartido, no los de cada usuario.
+*/
+async fn log_store_stats(interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // el primer tick sale enseguida; lo salteamos para no loguear justo al arrancar
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let (todo_count, completed, completion_ratio) = store_stats(&STORE.list().await);
+        tracing::info!(todo_count, completed, completion_ratio, "store stats");
+    }
+}
+
+// lo que loguea log_store_stats: (todos sin los borrados, cuantos de esos estan completos, completos / todos)
+pub fn store_stats(todos: &[Todo]) -> (usize, usize, f64) {
+    let live: Vec<&Todo> = todos.iter().filter(|todo| !todo.is_deleted()).collect();
+    let completed = live.iter().filter(|todo| todo.completed).count();
+    let completion_ratio = if live.is_empty() { 0.0 } else { completed as f64 / live.len() as f64 };
+    (live.len(), completed, completion_ratio)
+}
+
+/*
+Cada interval le pasa al webhook una copia de los todos (del store compartido y de cada usuario cargado en modo
+multi-tenant); el webhook decide cuales acaban de vencer. Los locks se sueltan antes de mandar los avisos.
+*/
+async fn watch_overdue(mut webhook: webhook::OverdueWebhook, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let tenants: Vec<(String, Arc<dyn TodoStore>)> = TENANT_STORES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(user, tenant)| (user.clone(), tenant.store.clone()))
+            .collect();
+        let mut todos: Vec<(Option<String>, Todo)> =
+            STORE.list().await.into_iter().map(|todo| (None, todo)).collect();
+        for (user, store) in tenants {
+            todos.extend(store.list().await.into_iter().map(|todo| (Some(user.clone()), todo)));
+        }
+        webhook.notify(&todos, Utc::now()).await;
+    }
+}
+
+// el valor de la variable name, o None si no esta definida; si no se puede parsear aborta, ver check_config
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = std::env::var(name).ok()?;
+    Some(value.parse().unwrap_or_else(|e| panic!("invalid {name} {value:?}: {e}")))
+}
+
+fn env_secs(name: &str, default: Duration) -> Duration {
+    env_parse(name).map_or(default, Duration::from_secs)
+}
+
+/*
+Service con todas las rutas y los middlewares globales. Los hoops van en el Service y no en el Router:
+asi tambien corren para requests que no coinciden con ninguna ruta, como los preflight OPTIONS de CORS.
+request_id va primero para que el tiempo medido incluya al resto de los middlewares.
+*/
+pub fn service() -> Service {
+    let router = Arc::new(route());
+    let mut service = Service::new(router.clone())
+        // el catcher corre cuando la respuesta es un error sin cuerpo, como el 404 de una ruta que no coincide
+        // pretty_json del router no ve los cuerpos que arma el catcher, que corre despues; lo repetimos aca
+        .catcher(
+            Catcher::default()
+                .hoop(middleware::pretty_json)
+                .hoop(MethodNotAllowed::new(router))
+                .hoop(middleware::route_not_found),
+        )
+        .hoop(middleware::request_id)
+        // metrics antes del rate limiter, asi los 429 tambien se cuentan como error
+        .hoop(metrics::track)
+        .hoop(cors_handler())
+        // corta por el Content-Length declarado antes de que cualquier hoop o handler lea el cuerpo
+        .hoop(ContentLengthLimit::from_env());
+    if let Some(security_headers) = SecurityHeaders::from_env() {
+        service = service.hoop(security_headers);
+    }
+    if let Some(limiter) = RateLimiter::from_env() {
+        service = service.hoop(limiter);
+    }
+    service
+}
+
+/*
+Espera Ctrl-C (o SIGTERM en Unix) y le pide al servidor un apagado ordenado:
+deja de aceptar conexiones nuevas y espera hasta SHUTDOWN_TIMEOUT a que terminen las que estan en curso.
+*/
+async fn shutdown_signal(handle: ServerHandle) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("received Ctrl-C"),
+        _ = terminate => tracing::info!("received SIGTERM"),
+    }
+
+    // los WebSocket y SSE de eventos no terminan solos, los cerramos para que el apagado no espere el timeout
+    events::shutdown();
+    handle.stop_graceful(SHUTDOWN_TIMEOUT);
+}
+
+/*
+Arma el middleware de CORS. Los origenes permitidos se leen de CORS_ALLOWED_ORIGINS,
+separados por coma. Si no se define se permite cualquier origen (*), lo cual sirve para desarrollo;
+en produccion conviene restringirlo, por ejemplo:
+CORS_ALLOWED_ORIGINS=https://app.example.com,https://admin.example.com
+*/
+fn cors_handler() -> CorsHandler {
+    let origins = std::env::var("CORS_ALLOWED_ORIGINS").unwrap_or_else(|_| "*".to_string());
+    let allow_origin = if origins.trim() == "*" {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.split(',').map(str::trim).filter(|o| !o.is_empty()).map(|origin| {
+            HeaderValue::from_str(origin)
+                .unwrap_or_else(|e| panic!("invalid origin {origin:?} in CORS_ALLOWED_ORIGINS: {e}"))
+        }))
+    };
+    tracing::info!(%origins, "cors allowed origins");
+
+    Cors::new()
+        .allow_origin(allow_origin)
+        .allow_methods(vec![
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers(vec![
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::ACCEPT,
+            header::IF_MATCH,
+            header::IF_NONE_MATCH,
+            header::IF_MODIFIED_SINCE,
+            header::HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+            header::HeaderName::from_static(tenant::USER_ID_HEADER),
+            header::HeaderName::from_static(middleware::REQUEST_ID_HEADER),
+        ])
+        // todo header propio que devolvemos tiene que estar aca o el navegador no lo deja leer
+        .expose_headers(vec![
+            header::ETAG,
+            header::LAST_MODIFIED,
+            header::LINK,
+            header::LOCATION,
+            header::RETRY_AFTER,
+            header::HeaderName::from_static("x-cache"),
+            header::HeaderName::from_static("x-limit"),
+            header::HeaderName::from_static("x-limit-clamped"),
+            header::HeaderName::from_static("x-next-cursor"),
+            header::HeaderName::from_static("x-total-count"),
+            header::HeaderName::from_static("idempotent-replayed"),
+            header::HeaderName::from_static(middleware::REQUEST_ID_HEADER),
+        ])
+        .into_handler()
+}
+
+/*
+Barras al final: el router de salvo parte el path en segmentos y descarta los vacios, asi que /todos/
+es la misma ruta que /todos (y /todos/1/ que /todos/1). Preferimos tratarlas como equivalentes antes que
+redirigir con un 308, que obliga a los clientes a seguir la redireccion (y a repetir el cuerpo de un POST).
+Las URLs que arma la API, como el Location de create_todo, van siempre sin la barra final.
+*/
+pub fn route() ->Router {
+    let mut router = Router::new();
+    if let Some(compression) = middleware::compression_from_env() {
+        router = router.hoop(compression);
+    }
+    // pretty_json y DebugBodies van despues de la compresion para ver las respuestas antes de comprimirlas
+    router = router.hoop(middleware::pretty_json);
+    if middleware::debug_bodies_enabled() {
+        tracing::warn!("DEBUG_BODIES is on, request and response bodies are logged");
+        router = router.hoop(middleware::DebugBodies::from_env());
+    }
+    // health, ready y metrics quedan fuera de /todos, asi no pasan por el limite de tamaño del cuerpo
+    let router = router
+        .push(todos_router())
+        .push(Router::with_path("health").get(health))
+        .push(Router::with_path("ready").get(ready))
+        .push(Router::with_path("metrics").get(get_metrics));
+
+    // el spec se arma a partir de las anotaciones #[endpoint] de las rutas de arriba
+    let doc = OpenApi::new("todo api", "0.1.0").merge_router(&router);
+    router
+        .push(doc.into_router("/api-doc/openapi.json"))
+        .push(SwaggerUi::new("/api-doc/openapi.json").into_router("swagger-ui"))
+        // GraphQL no entra en el spec de OpenAPI, tiene su propio esquema
+        .push(graphql_router())
+}
+
+// POST /graphql lleva el mismo limite de cuerpo y la misma API key que /todos, las mutaciones tambien escriben
+fn graphql_router() -> Router {
+    let mut router = Router::with_path("graphql").hoop(BodySizeLimit::from_env());
+    if let Some(auth) = ApiKeyAuth::from_env() {
+        router = router.hoop(auth);
+    }
+    router
+        .hoop(middleware::tenant_scope)
+        .post(graphql::graphql)
+        .push(Router::with_path("playground").get(graphql::playground))
+}
+
+fn todos_router() -> Router {
+    let body_limit = BodySizeLimit::from_env();
+    let bulk_limit = BodySizeLimit::bulk_from_env();
+    // parse_body tiene su propio limite global (64 KiB por defecto), lo igualamos al mayor de los nuestros
+    // para que un limite mas grande tambien se pueda parsear
+    salvo::http::request::set_secure_max_size(body_limit.max().max(bulk_limit.max()) as usize);
+    let mut router = Router::with_path("todos");
+    // con API_KEY definida, las rutas de /todos que modifican datos piden la key
+    if let Some(auth) = ApiKeyAuth::from_env() {
+        router = router.hoop(auth);
+    }
+    router
+    .hoop(middleware::tenant_scope)
+    // los endpoints que reciben muchos todos de una vez tienen su propio limite de cuerpo, mas grande
+    .push(
+        Router::new()
+        .hoop(bulk_limit)
+        .push(
+            Router::with_path("batch")
+            .post(create_todos_batch)
+            .patch(complete_todos_batch)
+        )
+        .push(Router::with_path("delete").post(delete_todos_batch))
+        .push(Router::with_path("import").post(import_todos))
+        // GET /todos/tags queda en el grupo de abajo, con el limite normal
+        .push(Router::with_path("tags").patch(tag_todos_batch))
+    )
+    .push(
+        Router::new()
+        .hoop(body_limit)
+        .get(list_todos)
+        .post(create_todo)
+        .delete(clear_todos)
+        // las rutas fijas van antes de <id>, si no "count" se tomaria como un id
+        .push(Router::with_path("count").get(count_todos))
+        .push(Router::with_path("tags").get(list_tags))
+        .push(Router::with_path("search").get(search_todos))
+        .push(Router::with_path("board").get(todo_board))
+        .push(Router::with_path("next-id").get(next_todo_id))
+        .push(Router::with_path("stats").get(todo_stats))
+        .push(Router::with_path("archive").post(archive_todos))
+        .push(Router::with_path("archived").get(list_archived))
+        .push(Router::with_path("events").get(todo_events))
+        .push(Router::with_path("stream").get(todo_stream))
+        .push(Router::with_path("seed").post(seed_todos))
+        .push(Router::with_path("export").get(export_todos))
+        .push(
+            Router::with_path("<id:num>")
+            .get(get_todo)
+            .put(update_todo)
+            .patch(patch_todo)
+            .delete(delete_todo)
+            .push(Router::with_path("toggle").post(toggle_todo))
+            .push(Router::with_path("move").post(move_todo))
+            .push(Router::with_path("reorder").post(reorder_todo))
+            .push(Router::with_path("restore").post(restore_todo))
+            .push(Router::with_path("children").get(list_children))
+            .push(Router::with_path("unarchive").post(unarchive_todo))
+            .push(Router::with_path("duplicate").post(duplicate_todo))
+        )
+    )
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(ListOptions),
+    responses(
+        (status_code = 200, description = "Page of todos", body = Vec<Todo>, content_type = ["application/json", "application/x-ndjson", "text/csv"]),
+        (status_code = 304, description = "No todo changed since If-Modified-Since"),
+        (status_code = 400, description = "Invalid query parameters, sort options, id range or fields", body = ApiError),
+    )
+)]
+pub async fn list_todos(req: &mut Request, res: &mut Response) {
+    //Esta línea parsea los parametros de la query (?offset=10&limit=5)
+    // un offset negativo o que no entra en usize (o cualquier parametro mal formado) es un bad request
+    let opts = match req.parse_queries::<ListOptions>() {
+        Ok(opts) => opts,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid query parameters");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request("invalid query parameters")));
+            return;
+        }
+    };
+    if let Err(message) = opts.validate() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(message)));
+        return;
+    }
+    let fields = match opts.fields.as_deref().map(parse_fields).transpose() {
+        Ok(fields) => fields,
+        Err(message) => {
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request(message)));
+            return;
+        }
+    };
+
+    // antes que los todos: si algo cambia en el medio, el Last-Modified queda viejo y no al reves
+    let last_changed = store().last_changed().await;
+    // los filtros se aplican antes de paginar, asi offset y limit cuentan sobre el conjunto filtrado
+    let mut todos: Vec<Todo> = store().list().await;
+    todos.retain(|todo| opts.matches(todo, case_sensitive_search()));
+    // el HashMap no tiene orden, asi que siempre ordenamos (por defecto id asc)
+    // para que la paginacion sea estable entre requests
+    if let Err(message) = opts.sort(&mut todos) {
+        tracing::debug!(%message, "invalid sort options");
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(message)));
+        return;
+    }
+    /*
+    Last-Modified es el ultimo cambio del store, cualquiera sea (tambien borrados, archivados o un import), asi un
+    todo que sale del filtro tambien cuenta. Si el cliente ya tiene esa version le mandamos 304 sin cuerpo.
+    El header solo tiene segundos: si el ultimo cambio es de este mismo segundo puede venir otro en el mismo
+    segundo que la fecha no distinga, asi que todavia no mandamos Last-Modified.
+    */
+    if last_changed.timestamp() < Utc::now().timestamp() {
+        res.add_header(header::LAST_MODIFIED, http_date(last_changed), true).ok();
+        if if_modified_since(req).is_some_and(|since| last_changed.timestamp() <= since.timestamp()) {
+            res.status_code(StatusCode::NOT_MODIFIED);
+            return;
+        }
+    }
+    // guardamos el total antes de paginar para que el cliente sepa cuantos hay en total
+    let total = todos.len();
+    let offset = opts.offset.unwrap_or(0);
+    let max_limit = *MAX_PAGE_LIMIT;
+    let limit = opts.limit.unwrap_or(*DEFAULT_LIMIT);
+    if limit > max_limit {
+        res.add_header("X-Limit-Clamped", max_limit, true).ok();
+    }
+    let limit = limit.min(max_limit);
+    res.add_header("X-Limit", limit, true).ok();
+    /*
+    Con ?cursor= la pagina arranca despues del id del cursor en vez de en offset: un todo que se crea o se borra
+    antes de ese punto no corre los demas, asi entre paginas no se repiten ni se saltean todos.
+    validate ya chequeo que se ordene por id, asi que el lugar se encuentra comparando ids.
+    */
+    let offset = match opts.cursor.as_deref().map(decode_cursor) {
+        Some(Ok(after)) => {
+            let descending = opts.order.as_deref() == Some("desc");
+            todos
+                .iter()
+                .position(|todo| if descending { todo.id < after } else { todo.id > after })
+                .unwrap_or(total)
+        }
+        _ => offset,
+    };
+    // A partir de aca, lo convertimos en un iterable, luego hace algunas cosas para la paginacion
+    // collect -> agarra los elementos restante y los guarda en un nuevo vector.
+    let todos: Vec<Todo> = todos
+    .into_iter()
+    .skip(offset)
+    .take(limit)
+    .collect();
+
+    res.add_header("X-Total-Count", total, true).ok();
+    if let Some(links) = pagination_links(req, offset, limit, total) {
+        res.add_header(header::LINK, links, true).ok();
+    }
+    // ordenando por id, si quedan mas todos despues de la pagina mandamos el cursor para pedir la siguiente
+    let next_cursor = match todos.last() {
+        Some(last) if opts.sort_field() == "id" && offset + todos.len() < total => Some(encode_cursor(last.id)),
+        _ => None,
+    };
+    if let Some(cursor) = &next_cursor {
+        res.add_header("X-Next-Cursor", cursor, true).ok();
+    }
+    let ndjson = opts.format.as_deref() == Some("ndjson") || accepts(req, NDJSON_CONTENT_TYPE);
+    // con ?format=csv devolvemos los mismos todos (filtrados y paginados) como archivo para planillas
+    if !ndjson && opts.format.as_deref() == Some("csv") {
+        res.add_header(header::CONTENT_TYPE, "text/csv; charset=utf-8", true).ok();
+        res.add_header(header::CONTENT_DISPOSITION, "attachment; filename=\"todos.csv\"", true).ok();
+        res.write_body(todos_to_csv(&todos)).ok();
+        return;
+    }
+    let paged = opts.paged.unwrap_or(false);
+    let limit = Some(limit);
+    match fields {
+        Some(fields) => {
+            let items = todos.iter().map(|todo| todo.select_fields(&fields)).collect();
+            render_page(res, PagedResponse { items, total, offset, limit, next_cursor }, ndjson, paged);
+        }
+        None => render_page(res, PagedResponse { items: todos, total, offset, limit, next_cursor }, ndjson, paged),
+    }
+}
+
+// manda una pagina de GET /todos como NDJSON, envuelta en un PagedResponse (?paged=true) o como arreglo JSON
+fn render_page<T>(res: &mut Response, page: PagedResponse<T>, ndjson: bool, paged: bool)
+where
+    T: serde::Serialize + Send + 'static,
+{
+    // con ?format=ndjson o Accept: application/x-ndjson mandamos un todo por linea a medida que se serializan,
+    // asi el cliente puede ir procesando sin esperar el arreglo completo
+    if ndjson {
+        res.add_header(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE, true).ok();
+        res.stream(futures_util::stream::iter(page.items.into_iter().map(|item| {
+            let mut line = serde_json::to_vec(&item)?;
+            line.push(b'\n');
+            Ok::<_, serde_json::Error>(line)
+        })));
+        return;
+    }
+    // con ?paged=true devolvemos el vector envuelto junto con los datos de paginacion
+    if paged {
+        res.render(Json(page));
+        return;
+    }
+    // renderizamos en un json el nuevo vector 
+    res.render(Json(page.items));
+}
+
+// fecha en el formato de los headers HTTP (Last-Modified), que solo tiene segundos
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+// fecha del If-Modified-Since del cliente; una fecha que no se entiende se ignora, como pide HTTP
+fn if_modified_since(req: &Request) -> Option<DateTime<Utc>> {
+    let value = req.header::<String>(header::IF_MODIFIED_SINCE)?;
+    DateTime::parse_from_rfc2822(&value)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+fn set_etag(res: &mut Response, etag: &str) {
+    res.add_header(header::ETAG, etag, true).ok();
+}
+
+// lista de ETags de un header If-Match / If-None-Match; los ETags debiles (W/"...") se comparan como fuertes
+fn etag_list(req: &Request, name: header::HeaderName) -> Option<Vec<String>> {
+    let value = req.header::<String>(name)?;
+    Some(
+        value
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/").to_string())
+            .collect(),
+    )
+}
+
+// true si el If-None-Match del cliente ya incluye la version actual
+fn if_none_match(req: &Request, etag: &str) -> bool {
+    etag_list(req, header::IF_NONE_MATCH)
+        .is_some_and(|tags| tags.iter().any(|tag| tag == "*" || tag == etag))
+}
+
+/*
+true si la request trae If-Match y no coincide con la version guardada (o el todo no existe).
+Asi dos clientes que editan el mismo todo no se pisan: el segundo recibe 412 y tiene que releerlo.
+*/
+fn if_match_fails(if_match: Option<&[String]>, current: Option<&Todo>) -> bool {
+    let Some(tags) = if_match else {
+        return false;
+    };
+    match current {
+        Some(todo) => {
+            let etag = todo.etag();
+            !tags.iter().any(|tag| tag == "*" || *tag == etag)
+        }
+        None => true,
+    }
+}
+
+// busca un todo que no este borrado: para los handlers un todo borrado es como si no existiera
+fn live_todo(todos: &HashMap<i64, Todo>, id: i64) -> Option<&Todo> {
+    todos.get(&id).filter(|todo| !todo.is_deleted())
+}
+
+fn live_todo_mut(todos: &mut HashMap<i64, Todo>, id: i64) -> Option<&mut Todo> {
+    todos.get_mut(&id).filter(|todo| !todo.is_deleted())
+}
+
+// borra un todo (logico o definitivo segun hard) y devuelve como quedo, o None si no existia
+fn remove_todo(todos: &mut HashMap<i64, Todo>, id: i64, hard: bool) -> Option<Todo> {
+    if hard {
+        return todos.remove(&id);
+    }
+    let todo = live_todo_mut(todos, id)?;
+    let now = Utc::now();
+    todo.deleted_at = Some(now);
+    todo.touch(now);
+    Some(todo.clone())
+}
+
+// lo que cambio un remove_with_children
+struct Removal {
+    // los borrados, el pedido primero
+    removed: Vec<Todo>,
+    // los hijos que pasaron a colgar del padre del borrado, ya con su version nueva
+    reparented: Vec<Todo>,
+}
+
+impl Removal {
+    fn publish(&self) {
+        for todo in &self.removed {
+            events::publish(EventKind::Deleted, todo);
+        }
+        for todo in self.reparented.iter().filter(|todo| !todo.is_deleted()) {
+            events::publish(EventKind::Updated, todo);
+        }
+    }
+}
+
+/*
+Borra el todo con remove_todo y se ocupa de sus hijos: con cascade borra toda la descendencia (no solo los
+hijos directos), si no los hijos pasan a colgar del padre del borrado, con version nueva como cualquier cambio.
+Devuelve None si el todo no existia.
+*/
+fn remove_with_children(todos: &mut HashMap<i64, Todo>, id: i64, hard: bool, cascade: bool) -> Option<Removal> {
+    let mut removed = vec![remove_todo(todos, id, hard)?];
+    let mut reparented = Vec::new();
+    if cascade {
+        let mut pending = vec![id];
+        while let Some(parent) = pending.pop() {
+            let children: Vec<i64> = todos
+                .values()
+                .filter(|todo| !todo.is_deleted() && todo.parent_id == Some(parent))
+                .map(|todo| todo.id)
+                .collect();
+            for child in children {
+                if let Some(todo) = remove_todo(todos, child, hard) {
+                    removed.push(todo);
+                    pending.push(child);
+                }
+            }
+        }
+    } else {
+        let grandparent = removed[0].parent_id;
+        let now = Utc::now();
+        for todo in todos.values_mut().filter(|todo| todo.parent_id == Some(id)) {
+            todo.parent_id = grandparent;
+            todo.touch(now);
+            reparented.push(todo.clone());
+        }
+    }
+    Some(Removal { removed, reparented })
+}
+
+// valida el parent_id de un todo contra el store, ver check_parent_with
+fn check_parent(todos: &HashMap<i64, Todo>, id: i64, parent_id: Option<i64>) -> Result<(), String> {
+    check_parent_with(|other| live_todo(todos, other).map(|todo| todo.parent_id), id, parent_id)
+}
+
+/*
+El parent tiene que existir y no puede ser el mismo todo ni uno de sus descendientes,
+si no se armaria un ciclo. parent_of devuelve el parent_id de un todo, o None si el todo no existe.
+*/
+fn check_parent_with(
+    parent_of: impl Fn(i64) -> Option<Option<i64>>,
+    id: i64,
+    parent_id: Option<i64>,
+) -> Result<(), String> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+    if parent_of(parent_id).is_none() {
+        return Err(format!("parent todo {parent_id} not found"));
+    }
+    // subimos desde el parent hasta la raiz; si en el camino aparece el propio todo hay un ciclo
+    let mut current = Some(parent_id);
+    let mut seen = HashSet::new();
+    while let Some(ancestor) = current {
+        if ancestor == id {
+            return Err(format!("parent todo {parent_id} would create a cycle"));
+        }
+        if !seen.insert(ancestor) {
+            break;
+        }
+        current = parent_of(ancestor).flatten();
+    }
+    Ok(())
+}
+
+// posicion siguiente a la ultima del orden manual
+fn next_position(todos: &HashMap<i64, Todo>) -> usize {
+    todos.values().map(|todo| todo.position + 1).max().unwrap_or(0)
+}
+
+/*
+Control de concurrencia optimista de PUT y PATCH: el cliente manda la version que leyo y el update del store
+falla con un conflicto si el todo cambio desde entonces (ver render_store_error). Si no la manda
+respondemos 400 y devolvemos None.
+*/
+fn require_version(res: &mut Response, version: Option<u64>) -> Option<u64> {
+    if version.is_none() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request("version is required")));
+    }
+    version
+}
+
+// respuesta para un error del store; en un conflicto de version va el todo actual para que el cliente re-aplique su cambio
+fn render_store_error(res: &mut Response, error: StoreError) {
+    tracing::debug!(%error, "store error");
+    match error {
+        StoreError::NotFound(id) => {
+            res.status_code(StatusCode::NOT_FOUND);
+            res.render(Json(ApiError::not_found(id)));
+        }
+        StoreError::IdTaken(id) => {
+            res.status_code(StatusCode::CONFLICT);
+            res.render(Json(ApiError::conflicting_ids(vec![id])));
+        }
+        StoreError::Conflict(current) => {
+            res.status_code(StatusCode::CONFLICT);
+            res.render(Json(current));
+        }
+    }
+}
+
+/*
+Con ?dry_run=true create, PUT, PATCH y DELETE hacen todos los chequeos pero no cambian el store: responden 200
+con el todo como quedaria (o con los todos que se borrarian). Los errores salen con el mismo status que sin dry_run.
+*/
+fn is_dry_run(req: &Request) -> bool {
+    req.query::<bool>("dry_run").unwrap_or(false)
+}
+
+// lo que haria store().update sin guardar nada: el mismo chequeo de version y el todo con la version siguiente
+fn preview_update(res: &mut Response, stored: &Todo, mut todo: Todo) {
+    if todo.version != stored.version {
+        render_store_error(res, StoreError::Conflict(Box::new(stored.clone())));
+        return;
+    }
+    todo.version += 1;
+    res.render(Json(todo));
+}
+
+/*
+Aplica el patch sobre una copia de stored (asi el guardado no se toca si el resultado no es valido),
+normaliza y valida el resultado y, si cambio el parent, lo chequea contra todos.
+El error trae el status a responder: 400 si el resultado no es valido; con JSON Patch, 409 si fallo
+una operacion test y 422 si fallo otra (por ejemplo un remove de un campo que no esta).
+*/
+fn patched_todo(
+    todos: &HashMap<i64, Todo>,
+    stored: &Todo,
+    patch: PatchBody,
+) -> Result<Todo, (StatusCode, String)> {
+    let bad_request = |message: String| (StatusCode::BAD_REQUEST, message);
+    let mut todo = match patch {
+        PatchBody::Fields(patch) => {
+            let mut todo = stored.clone();
+            patch.apply(&mut todo);
+            todo
+        }
+        PatchBody::Merge(document) => stored.merge_patch(&document).map_err(bad_request)?,
+        PatchBody::Json(ops) => stored.json_patch(&ops).map_err(|e| {
+            let status = match e.kind {
+                json_patch::PatchErrorKind::TestFailed => StatusCode::CONFLICT,
+                _ => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            (status, format!("json patch failed: {e}"))
+        })?
+        .map_err(bad_request)?,
+    };
+    todo.normalize(*COLLAPSE_WHITESPACE);
+    todo.validate().map_err(|e| bad_request(e.to_string()))?;
+    if todo.parent_id != stored.parent_id {
+        check_parent(todos, todo.id, todo.parent_id).map_err(bad_request)?;
+    }
+    Ok(todo)
+}
+
+// foto del store indexada por id, para poder usar live_todo, check_parent y next_position sobre ella
+async fn snapshot() -> HashMap<i64, Todo> {
+    store().list().await.into_iter().map(|todo| (todo.id, todo)).collect()
+}
+
+/*
+Lee el todo, le aplica change y lo guarda. Si otra request lo cambio en el medio el update falla por version
+y volvemos a empezar con el todo nuevo. change devuelve None para tratar al todo como inexistente
+y Some(false) si no hay nada que cambiar. Devuelve el todo como quedo y si cambio.
+*/
+async fn modify_todo(id: i64, mut change: impl FnMut(&mut Todo) -> Option<bool>) -> Option<(Todo, bool)> {
+    loop {
+        let mut todo = store().get(id).await?;
+        let was_completed = todo.completed;
+        if !change(&mut todo)? {
+            return Some((todo, false));
+        }
+        let now = Utc::now();
+        todo.track_completion(was_completed, now);
+        todo.updated_at = now;
+        match store().update(todo).await {
+            Ok(todo) => return Some((todo, true)),
+            Err(StoreError::Conflict(_)) => continue,
+            Err(_) => return None,
+        }
+    }
+}
+
+// guarda la siguiente ocurrencia del todo recurrente completed (ver Todo::next_occurrence) al final del orden
+fn insert_next_occurrence(todos: &mut HashMap<i64, Todo>, completed: &Todo, recurrence: String) -> Todo {
+    let mut next = completed.next_occurrence(NEXT_ID.fetch_add(1, Ordering::Relaxed), recurrence, Utc::now());
+    next.position = next_position(todos);
+    todos.insert(next.id, next.clone());
+    events::publish(EventKind::Created, &next);
+    next
+}
+
+/*
+Lo mismo, para los handlers que completan un solo todo con store().update. No cuenta contra MAX_TODOS:
+la ocurrencia nueva toma el lugar de la que se termino, y fallar despues de completar la dejaria a medias.
+*/
+async fn create_next_occurrence(completed: &Todo, recurrence: String) -> Todo {
+    store()
+        .transaction(|todos| insert_next_occurrence(todos, completed, recurrence))
+        .await
+}
+
+// responde el todo cambiado o, si completo un todo recurrente, el todo junto con su siguiente ocurrencia
+fn render_completed(res: &mut Response, todo: Todo, next: Option<Todo>) {
+    match next {
+        Some(next) => res.render(Json(CompletedRecurrence { completed: todo, next })),
+        None => res.render(Json(todo)),
+    }
+}
+
+fn content_type_is(req: &Request, essence: &str) -> bool {
+    req.content_type().is_some_and(|mime| mime.essence_str() == essence)
+}
+
+/*
+Los handlers que leen un cuerpo solo entienden JSON. Si el Content-Type no es application/json
+respondemos 415 en vez de dejar que parse_body falle con un error confuso.
+*/
+fn require_json(req: &Request, res: &mut Response) -> bool {
+    let is_json = content_type_is(req, "application/json");
+    if !is_json {
+        tracing::debug!(content_type = ?req.content_type(), "unsupported content type");
+        res.status_code(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        res.render(Json(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "content type must be application/json",
+        )));
+    }
+    is_json
+}
+
+// repite la respuesta original de un create_todo con la misma Idempotency-Key
+fn replay_created(res: &mut Response, todo: Todo) {
+    tracing::debug!(id = todo.id, "replaying idempotent create");
+    res.status_code(StatusCode::CREATED);
+    set_location(res, todo.id);
+    res.add_header("Idempotent-Replayed", "true", true).ok();
+    res.render(Json(todo));
+}
+
+/*
+El <id> del path. La ruta ya pide digitos (<id:num>), asi que "abc" ni llega aca y responde el 404 del catcher;
+solo falta rechazar un numero que no entra en un i64.
+*/
+fn path_id(req: &mut Request, res: &mut Response) -> Option<i64> {
+    let id = req.param::<i64>("id");
+    if id.is_none() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request("id must be an integer that fits in 64 bits")));
+    }
+    id
+}
+
+// header Location de un todo recien creado, asi el cliente sabe donde quedo sin armar la URL
+fn set_location(res: &mut Response, id: i64) {
+    res.add_header(header::LOCATION, format!("/todos/{id}"), true).ok();
+}
+
+/*
+Arma el CSV de los todos con una fila de encabezado id,text,completed.
+*/
+fn todos_to_csv(todos: &[Todo]) -> String {
+    let mut csv = String::from("id,text,completed\r\n");
+    for todo in todos {
+        csv.push_str(&format!("{},{},{}\r\n", todo.id, csv_field(&todo.text), todo.completed));
+    }
+    csv
+}
+
+// un campo con coma, comillas o saltos de linea va entre comillas, duplicando las comillas internas
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// indica si el header Accept de la request incluye el content type pedido
+fn accepts(req: &Request, content_type: &str) -> bool {
+    req.header::<String>(header::ACCEPT)
+        .is_some_and(|accept| accept.split(',').any(|part| part.trim().starts_with(content_type)))
+}
+
+/*
+Header Link (RFC 5988) con las paginas first, prev, next y last, asi un cliente HTTP generico puede recorrer
+la lista sin conocer offset y limit. Las URLs mantienen el resto de la query (filtros, orden, formato) y
+solo cambian offset y limit; en la primera pagina no va prev y en la ultima no va next.
+*/
+fn pagination_links(req: &Request, offset: usize, limit: usize, total: usize) -> Option<String> {
+    // con limit=0 no hay paginas que recorrer
+    if limit == 0 {
+        return None;
+    }
+    let path = req.uri().path();
+    let others: Vec<&str> = req
+        .uri()
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| {
+            let name = pair.split('=').next().unwrap_or("");
+            // los links son por offset, asi que el cursor no va (offset y cursor juntos son un bad request)
+            !pair.is_empty() && name != "offset" && name != "limit" && name != "cursor"
+        })
+        .collect();
+    let link = |offset: usize, rel: &str| {
+        let mut query = others.clone();
+        let page = format!("offset={offset}&limit={limit}");
+        query.push(&page);
+        format!("<{path}?{}>; rel=\"{rel}\"", query.join("&"))
+    };
+    let last = total.saturating_sub(1) / limit * limit;
+    let mut links = vec![link(0, "first")];
+    if offset > 0 {
+        links.push(link(offset.saturating_sub(limit).min(last), "prev"));
+    }
+    if offset + limit < total {
+        links.push(link(offset + limit, "next"));
+    }
+    links.push(link(last, "last"));
+    Some(links.join(", "))
+}
+
+#[endpoint(
+    tags("todos"),
+    responses((status_code = 200, description = "Totals by completion status", body = TodoCounts))
+)]
+pub async fn count_todos(res: &mut Response) {
+    let todos = store().list().await;
+
+    // una sola pasada sobre la misma foto del store
+    let mut counts = TodoCounts::default();
+    for todo in todos.iter().filter(|todo| !todo.is_deleted()) {
+        counts.add(todo);
+    }
+    res.render(Json(counts));
+}
+
+#[endpoint(
+    tags("todos"),
+    responses((status_code = 200, description = "Totals by status and by priority", body = TodoStats))
+)]
+pub async fn todo_stats(res: &mut Response) {
+    let todos = store().list().await;
+
+    // igual que count_todos, todo se calcula en una pasada sobre la misma lista
+    let mut stats = TodoStats::default();
+    for todo in todos.iter().filter(|todo| !todo.is_deleted()) {
+        stats.counts.add(todo);
+        if let Some(priority) = todo.priority {
+            *stats.by_priority.entry(priority).or_default() += 1;
+        }
+    }
+    res.render(Json(stats));
+}
+
+#[endpoint(
+    tags("todos"),
+    responses((status_code = 200, description = "Distinct tags in use, sorted", body = Vec<String>))
+)]
+pub async fn list_tags(res: &mut Response) {
+    let todos = store().list().await;
+
+    // BTreeSet descarta repetidos y los deja ordenados
+    let tags: BTreeSet<&str> = todos
+        .iter()
+        .filter(|todo| !todo.is_deleted())
+        .flat_map(|todo| todo.tags.iter().map(String::as_str))
+        .collect();
+    res.render(Json(tags));
+}
+
+/*
+Un id libre para los clientes que mandan sus propios ids (POST /todos/batch, PUT con upsert): el siguiente
+al mayor guardado (contando los borrados logicamente), y nunca uno que ya se haya dado a un create automatico.
+Es solo orientativo: otra request puede usar el mismo id antes, y ahi el create choca con un 409. Por eso,
+si el cliente no necesita elegir el id, es mejor dejar que lo asigne POST /todos.
+*/
+#[endpoint(
+    tags("todos"),
+    responses((status_code = 200, description = "An id that is free right now (advisory)", body = NextId))
+)]
+pub async fn next_todo_id(res: &mut Response) {
+    let max_id = store().list().await.iter().map(|todo| todo.id).max().unwrap_or(0);
+    let next_id = NEXT_ID.load(Ordering::Relaxed).max(max_id + 1);
+    res.render(Json(NextId { next_id }));
+}
+
+/*
+Las dos columnas de un tablero (pendientes y terminados) en una sola request. Acepta los mismos filtros y orden
+que GET /todos (q, tag, min_priority, ...), pero no pagina: cada columna trae todos los que pasan los filtros.
+Las dos salen de la misma lista, asi un todo que se completa en el medio no aparece en ambas ni en ninguna.
+*/
+#[endpoint(
+    tags("todos"),
+    parameters(ListOptions),
+    responses(
+        (status_code = 200, description = "Filtered todos split into pending and completed", body = TodoBoard),
+        (status_code = 400, description = "Invalid query parameters or sort options", body = ApiError),
+    )
+)]
+pub async fn todo_board(req: &mut Request, res: &mut Response) {
+    let opts = match req.parse_queries::<ListOptions>() {
+        Ok(opts) => opts,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid query parameters");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request("invalid query parameters")));
+            return;
+        }
+    };
+    if let Err(message) = opts.validate() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(message)));
+        return;
+    }
+
+    let mut board = TodoBoard::default();
+    for todo in store().list().await.into_iter().filter(|todo| opts.matches(todo, case_sensitive_search())) {
+        if todo.completed {
+            board.completed.push(todo);
+        } else {
+            board.pending.push(todo);
+        }
+    }
+    for column in [&mut board.pending, &mut board.completed] {
+        if let Err(message) = opts.sort(column) {
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request(message)));
+            return;
+        }
+    }
+    res.render(Json(board));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(SearchOptions),
+    responses(
+        (status_code = 200, description = "Matching todos, best first, with their score", body = Vec<SearchResult>),
+        (status_code = 400, description = "Missing q or invalid query parameters", body = ApiError),
+    )
+)]
+pub async fn search_todos(req: &mut Request, res: &mut Response) {
+    let opts = match req.parse_queries::<SearchOptions>() {
+        Ok(opts) => opts,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid query parameters");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request("invalid query parameters")));
+            return;
+        }
+    };
+    let Some(q) = opts.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) else {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request("q must not be empty")));
+        return;
+    };
+    let limit = opts.limit.unwrap_or(*SEARCH_MAX_RESULTS).min(*SEARCH_MAX_RESULTS);
+
+    // los mismos todos que ve GET /todos sin filtros: ni borrados ni archivados
+    let visible = ListOptions::default();
+    let mut results: Vec<SearchResult> = store()
+        .list()
+        .await
+        .into_iter()
+        .filter(|todo| visible.matches(todo, case_sensitive_search()))
+        .filter_map(|todo| {
+            let score = todo.search_score(q, case_sensitive_search());
+            (score > 0).then_some(SearchResult { score, todo })
+        })
+        .collect();
+    // a igual puntaje, por id para que el orden sea estable
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(a.todo.id.cmp(&b.todo.id)));
+    results.truncate(limit);
+    res.render(Json(results));
+}
+
+/*
+WebSocket que manda un TodoEvent en JSON por cada create/update/delete.
+Los mensajes que manda el cliente se ignoran, solo los leemos para darnos cuenta de cuando se desconecta.
+*/
+#[handler]
+pub async fn todo_events(req: &mut Request, res: &mut Response) -> Result<(), StatusError> {
+    let mut receiver = events::subscribe();
+    WebSocketUpgrade::new()
+        .upgrade(req, res, |mut ws| async move {
+            tracing::debug!("events client connected");
+            // igual que en /todos/stream, un ping periodico evita que IDLE_TIMEOUT_SECS cierre la conexion
+            let mut keep_alive = tokio::time::interval(events::KEEP_ALIVE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = keep_alive.tick() => {
+                        if ws.send(Message::ping(Vec::new())).await.is_err() {
+                            break;
+                        }
+                    }
+                    event = receiver.recv() => match event {
+                        Ok(event) => {
+                            let Ok(text) = serde_json::to_string(&event) else { continue };
+                            if ws.send(Message::text(text)).await.is_err() {
+                                break;
+                            }
+                        }
+                        // el cliente es mas lento que los cambios: se pierde los eventos viejos pero sigue conectado
+                        Err(RecvError::Lagged(skipped)) => {
+                            tracing::debug!(skipped, "events client lagged");
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    message = ws.recv() => match message {
+                        Some(Ok(message)) if !message.is_close() => {}
+                        _ => break,
+                    },
+                    _ = events::closed() => {
+                        ws.send(Message::close()).await.ok();
+                        break;
+                    }
+                }
+            }
+            tracing::debug!("events client disconnected");
+        })
+        .await
+}
+
+/*
+Lo mismo que /todos/events pero con Server-Sent Events: cada cambio llega como un evento
+con nombre created/updated/deleted y el todo en JSON como data. El stream se corta cuando
+el cliente se desconecta (se descarta el receiver) o cuando el servidor se apaga.
+*/
+#[handler]
+pub async fn todo_stream(res: &mut Response) {
+    let stream = futures_util::stream::unfold(events::subscribe(), |mut receiver| async move {
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        let sse = SseEvent::default().name(event.kind.as_str()).json(&event.todo);
+                        return Some((sse, receiver));
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::debug!(skipped, "stream client lagged");
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = events::closed() => return None,
+            }
+        }
+    });
+    // mandamos un comentario cada tanto para que IDLE_TIMEOUT_SECS no corte el stream cuando no hay cambios
+    SseKeepAlive::new(stream)
+        .max_interval(events::KEEP_ALIVE_INTERVAL)
+        .stream(res);
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(
+        ("id" = i64, Path, description = "todo id"),
+        ("fields" = Option<String>, Query, description = "comma separated todo fields to return, e.g. id,text"),
+    ),
+    responses(
+        (status_code = 200, description = "The todo", body = Todo),
+        (status_code = 304, description = "The todo did not change since the ETag in If-None-Match"),
+        (status_code = 400, description = "Unknown name in fields", body = ApiError),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+    )
+)]
+pub async fn get_todo(req: &mut Request, res: &mut Response) {
+    // id de los parametros
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    tracing::debug!(?id, "get todo");
+    // ?fields=id,text como en GET /todos
+    let fields = req.query::<String>("fields");
+    let fields = match fields.as_deref().map(parse_fields).transpose() {
+        Ok(fields) => fields,
+        Err(message) => {
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request(message)));
+            return;
+        }
+    };
+
+    // buscamos el todo por su clave; X-Cache cuenta si salio del cache del store
+    let (todo, hit) = store().cached_get(id).await;
+    res.add_header("X-Cache", if hit { "HIT" } else { "MISS" }, true).ok();
+    match todo.filter(|todo| !todo.is_deleted()) {
+        Some(todo) => {
+            let etag = todo.etag();
+            // si el cliente ya tiene esta version no hace falta mandarla de nuevo
+            if if_none_match(req, &etag) {
+                res.status_code(StatusCode::NOT_MODIFIED);
+                set_etag(res, &etag);
+                return;
+            }
+            set_etag(res, &etag);
+            match fields {
+                Some(fields) => res.render(Json(todo.select_fields(&fields))),
+                None => res.render(Json(todo)),
+            }
+        }
+        None => {
+            tracing::debug!(?id, "todo is not found");
+            res.status_code(StatusCode::NOT_FOUND);
+            res.render(Json(ApiError::not_found(id)));
+        }
+    }
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(
+        ("Idempotency-Key" = Option<String>, Header, description = "retrying with the same key returns the todo created the first time"),
+        ("dedupe" = Option<bool>, Query, description = "reject the todo if a pending one already has the same text"),
+        ("dry_run" = Option<bool>, Query, description = "run every check and return the result with 200 without changing anything"),
+    ),
+    request_body = NewTodo,
+    responses(
+        (status_code = 201, description = "Created todo with its assigned id. A replay (Idempotency-Key, or the same body again within CREATE_DEDUP_WINDOW_SECS) returns the first todo with Idempotent-Replayed: true", body = Todo),
+        (status_code = 200, description = "The todo that would be created (only with dry_run=true)", body = Todo),
+        (status_code = 400, description = "Invalid request body", body = ApiError),
+        (status_code = 409, description = "A pending todo with the same text exists (only with dedupe=true), or the assigned id is already taken (ids has it)", body = ApiError),
+        (status_code = 507, description = "The store already holds MAX_TODOS todos", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+    )
+)]
+pub async fn create_todo(req: &mut Request, res: &mut Response) {
+    // el cliente ya no manda el id, lo asigna el servidor
+    // si el cuerpo no es un NewTodo valido respondemos bad request en vez de entrar en panic
+    if !require_json(req, res) {
+        return;
+    }
+    let new_todo = match req.parse_body::<NewTodo>().await {
+        Ok(todo) => todo,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    // linea que registra mensajes de depuracion
+    tracing::debug!(todo = ?new_todo, "create_todo");
+
+    let dry_run = is_dry_run(req);
+    // solo se recuerdan las creaciones exitosas: un cuerpo invalido se puede reintentar con la misma key.
+    // un dry run no crea nada, asi que no usa la key
+    // en modo multi-tenant la key es de cada usuario, asi uno no recibe el todo que creo otro
+    let idempotency_key = req
+        .header::<String>(IDEMPOTENCY_KEY_HEADER)
+        .filter(|key| !key.is_empty() && !dry_run)
+        .map(|key| match tenant::current() {
+            Some(user) => format!("{user}/{key}"),
+            None => key,
+        });
+    if let Some(todo) = idempotency_key.as_deref().and_then(|key| IDEMPOTENCY.get(key)) {
+        replay_created(res, todo);
+        return;
+    }
+
+    // el id definitivo se toma recien cuando pasaron todos los chequeos, asi un 400, 409 o 507 no gasta uno.
+    // hasta entonces el todo lleva el que le tocaria si se creara ahora, que es lo que muestra un dry run
+    let mut todo = new_todo.into_todo(NEXT_ID.load(Ordering::Relaxed));
+    todo.normalize(*COLLAPSE_WHITESPACE);
+    if let Err(e) = todo.validate() {
+        tracing::debug!(error = %e, "invalid todo");
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(e.to_string())));
+        return;
+    }
+
+    let _create = CREATE_LOCK.lock().await;
+    // otra request con la misma key pudo terminar mientras esperabamos el lock
+    if let Some(todo) = idempotency_key.as_deref().and_then(|key| IDEMPOTENCY.get(key)) {
+        replay_created(res, todo);
+        return;
+    }
+    // con una Idempotency-Key manda la key: dos keys distintas son dos todos aunque el cuerpo sea igual.
+    // se chequea con el lock tomado, asi dos POST iguales que llegan juntos no crean dos
+    let dedup = create_dedup_window()
+        .filter(|_| idempotency_key.is_none() && !dry_run)
+        .map(|window| (create_dedup_key(&todo), window));
+    if let Some(recent) = dedup.as_ref().and_then(|(key, _)| RECENT_CREATES.get(key)) {
+        replay_created(res, recent);
+        return;
+    }
+
+    let todos = snapshot().await;
+    if let Err(e) = check_capacity(todos.len(), 1) {
+        res.status_code(StatusCode::INSUFFICIENT_STORAGE);
+        res.render(Json(e));
+        return;
+    }
+
+    if let Err(message) = check_parent(&todos, todo.id, todo.parent_id) {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(message)));
+        return;
+    }
+
+    // con ?dedupe=true no dejamos crear un todo igual a uno pendiente (sin importar espacios ni, salvo con
+    // CASE_SENSITIVE_SEARCH, mayusculas)
+    if req.query::<bool>("dedupe").unwrap_or(false) {
+        let case_sensitive = case_sensitive_search();
+        let text = todo.normalized_text(case_sensitive);
+        if let Some(existing) = todos
+            .values()
+            .find(|other| !other.is_deleted() && !other.completed && other.normalized_text(case_sensitive) == text)
+        {
+            tracing::debug!(id = existing.id, "duplicate todo text");
+            res.status_code(StatusCode::CONFLICT);
+            res.render(Json(ApiError::duplicate_text(existing.id)));
+            return;
+        }
+    }
+
+    // los todos nuevos van al final del orden manual
+    todo.position = next_position(&todos);
+    if dry_run {
+        res.render(Json(todo));
+        return;
+    }
+    // fetch_add devuelve el valor actual y lo incrementa de forma atomica, asi dos creaciones concurrentes
+    // nunca reciben el mismo id (el batch y las ocurrencias recurrentes lo toman sin CREATE_LOCK)
+    todo.id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    // se agrega el nuevo todo al store
+    let todo = match store().create(todo).await {
+        Ok(todo) => todo,
+        Err(e) => {
+            render_store_error(res, e);
+            return;
+        }
+    };
+    events::publish(EventKind::Created, &todo);
+    if let Some(key) = idempotency_key {
+        IDEMPOTENCY.insert(key, todo.clone());
+    }
+    if let Some((key, window)) = dedup {
+        RECENT_CREATES.insert_for(key, todo.clone(), window);
+    }
+    // status code de creado, Location apunta al nuevo recurso y devolvemos el todo con su id asignado
+    res.status_code(StatusCode::CREATED);
+    set_location(res, todo.id);
+    res.render(Json(todo));
+}
+
+#[endpoint(
+    tags("todos"),
+    request_body = Vec<BatchTodo>,
+    responses(
+        (status_code = 201, description = "All todos were created", body = Vec<Todo>),
+        (status_code = 400, description = "Invalid request body", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+        (status_code = 409, description = "Some ids already exist or are repeated", body = ApiError),
+        (status_code = 507, description = "The batch does not fit under MAX_TODOS", body = ApiError),
+    )
+)]
+pub async fn create_todos_batch(req: &mut Request, res: &mut Response) {
+    // en el batch los ids vienen del cliente, como en una importacion
+    if !require_json(req, res) {
+        return;
+    }
+    let mut new_todos: Vec<Todo> = match req.parse_body::<Vec<BatchTodo>>().await {
+        Ok(todos) => todos.into_iter().map(|batch| batch.todo.into_todo(batch.id)).collect(),
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    tracing::debug!(count = new_todos.len(), "create todos batch");
+
+    for todo in &mut new_todos {
+        todo.normalize(*COLLAPSE_WHITESPACE);
+        if let Err(e) = todo.validate() {
+            tracing::debug!(error = %e, id = todo.id, "invalid todo in batch");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request(format!("todo {}: {e}", todo.id))));
+            return;
+        }
+    }
+
+    let inserted = store()
+        .transaction(|todos| {
+            check_capacity(todos.len(), new_todos.len())?;
+
+            // juntamos los ids que ya existen en el store o que se repiten dentro del mismo batch
+            let mut seen = HashSet::new();
+            let mut conflicts: Vec<i64> = new_todos
+                .iter()
+                .map(|todo| todo.id)
+                .filter(|id| todos.contains_key(id) || !seen.insert(*id))
+                .collect();
+            if !conflicts.is_empty() {
+                conflicts.sort_unstable();
+                conflicts.dedup();
+                tracing::debug!(?conflicts, "batch has conflicting ids");
+                return Err(ApiError::conflicting_ids(conflicts));
+            }
+
+            // el parent puede estar en el store o ser otro todo del mismo batch
+            let batch_parents: HashMap<i64, Option<i64>> =
+                new_todos.iter().map(|todo| (todo.id, todo.parent_id)).collect();
+            let parent_of = |id: i64| {
+                batch_parents
+                    .get(&id)
+                    .copied()
+                    .or_else(|| live_todo(todos, id).map(|todo| todo.parent_id))
+            };
+            for todo in &new_todos {
+                check_parent_with(parent_of, todo.id, todo.parent_id)
+                    .map_err(|message| ApiError::bad_request(format!("todo {}: {message}", todo.id)))?;
+            }
+
+            // todo o nada: recien aca insertamos, cuando ya sabemos que no hay conflictos
+            let first_position = next_position(todos);
+            for (offset, todo) in new_todos.iter_mut().enumerate() {
+                todo.position = first_position + offset;
+                todos.insert(todo.id, todo.clone());
+            }
+            Ok(())
+        })
+        .await;
+    if let Err(e) = inserted {
+        res.status_code(e.status());
+        res.render(Json(e));
+        return;
+    }
+    // movemos el contador para que los ids automaticos no choquen con los del batch
+    if let Some(max_id) = new_todos.iter().map(|todo| todo.id).max() {
+        NEXT_ID.fetch_max(max_id + 1, Ordering::Relaxed);
+    }
+    for todo in &new_todos {
+        events::publish(EventKind::Created, todo);
+    }
+    res.status_code(StatusCode::CREATED);
+    res.render(Json(new_todos));
+}
+
+#[endpoint(
+    tags("todos"),
+    request_body = BatchComplete,
+    responses(
+        (status_code = 200, description = "Which ids were updated, which already had that completed and which were not found", body = BatchUpdateResult),
+        (status_code = 400, description = "Invalid request body or empty ids", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+    )
+)]
+pub async fn complete_todos_batch(req: &mut Request, res: &mut Response) {
+    if !require_json(req, res) {
+        return;
+    }
+    let mut batch = match req.parse_body::<BatchComplete>().await {
+        Ok(batch) => batch,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    if batch.ids.is_empty() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request("ids must not be empty")));
+        return;
+    }
+    tracing::debug!(?batch, "complete todos batch");
+    // un id repetido se cuenta una sola vez
+    let mut seen = HashSet::new();
+    batch.ids.retain(|id| seen.insert(*id));
+
+    // una sola transaccion para todo el batch: nadie ve el batch aplicado a medias
+    let result = store()
+        .transaction(|todos| {
+            let now = Utc::now();
+            let mut result = BatchUpdateResult::default();
+            for id in batch.ids {
+                match live_todo_mut(todos, id) {
+                    // los que ya estaban asi no se tocan: ni version nueva ni evento
+                    Some(todo) if todo.completed == batch.completed => result.unchanged.push(id),
+                    Some(todo) => {
+                        let was_completed = todo.completed;
+                        todo.completed = batch.completed;
+                        todo.track_completion(was_completed, now);
+                        let recurrence = todo.end_recurrence(was_completed);
+                        todo.touch(now);
+                        events::publish(EventKind::Updated, todo);
+                        result.updated.push(id);
+                        if let Some(recurrence) = recurrence {
+                            let todo = todo.clone();
+                            result.created.push(insert_next_occurrence(todos, &todo, recurrence).id);
+                        }
+                    }
+                    None => result.missing.push(id),
+                }
+            }
+            result
+        })
+        .await;
+    res.render(Json(result));
+}
+
+#[endpoint(
+    tags("todos"),
+    request_body = BatchTags,
+    responses(
+        (status_code = 200, description = "Which ids got new tags, which already had them and which were not found", body = BatchTagsResult),
+        (status_code = 400, description = "Invalid request body, empty ids, no tags to add or remove, or a tag in both lists", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+    )
+)]
+pub async fn tag_todos_batch(req: &mut Request, res: &mut Response) {
+    if !require_json(req, res) {
+        return;
+    }
+    let mut batch = match req.parse_body::<BatchTags>().await {
+        Ok(batch) => batch,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    if batch.ids.is_empty() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request("ids must not be empty")));
+        return;
+    }
+    // los tags se comparan ya normalizados, como quedan guardados en los todos
+    let normalize = |tags: &[String]| tags.iter().map(|tag| normalize_tag(tag)).collect::<Option<Vec<String>>>();
+    let (Some(add), Some(remove)) = (normalize(&batch.add), normalize(&batch.remove)) else {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request("tags must not be empty")));
+        return;
+    };
+    if add.is_empty() && remove.is_empty() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request("add or remove must list at least one tag")));
+        return;
+    }
+    if let Some(tag) = add.iter().find(|tag| remove.contains(*tag)) {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(format!("tag {tag:?} is in both add and remove"))));
+        return;
+    }
+    tracing::debug!(?batch, "tag todos batch");
+    // un id repetido se cuenta una sola vez
+    let mut seen = HashSet::new();
+    batch.ids.retain(|id| seen.insert(*id));
+
+    // como PATCH /todos/batch: una sola transaccion, nadie ve los tags cambiados a medias
+    let result = store()
+        .transaction(|todos| {
+            let now = Utc::now();
+            let mut result = BatchTagsResult::default();
+            for id in batch.ids {
+                let Some(todo) = live_todo_mut(todos, id) else {
+                    result.missing.push(id);
+                    continue;
+                };
+                let before = todo.tags.clone();
+                todo.tags.retain(|tag| !remove.contains(tag));
+                todo.tags.extend(add.iter().cloned());
+                todo.normalize_tags();
+                if todo.tags == before {
+                    result.unchanged.push(id);
+                    continue;
+                }
+                todo.touch(now);
+                events::publish(EventKind::Updated, todo);
+                result.updated.push(id);
+            }
+            result
+        })
+        .await;
+    res.render(Json(result));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(("hard" = Option<bool>, Query, description = "remove the todos for good instead of marking them deleted")),
+    request_body = BatchDelete,
+    responses(
+        (status_code = 200, description = "Which ids were deleted and which were not found", body = BatchDeleteResult),
+        (status_code = 400, description = "Invalid request body or empty ids", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+    )
+)]
+pub async fn delete_todos_batch(req: &mut Request, res: &mut Response) {
+    // a diferencia de DELETE /todos (que vacia todo), aca se borran solo los ids del cuerpo
+    if !require_json(req, res) {
+        return;
+    }
+    let mut batch = match req.parse_body::<BatchDelete>().await {
+        Ok(batch) => batch,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    if batch.ids.is_empty() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request("ids must not be empty")));
+        return;
+    }
+    tracing::debug!(?batch, "delete todos batch");
+    // un id repetido se cuenta una sola vez
+    let mut seen = HashSet::new();
+    batch.ids.retain(|id| seen.insert(*id));
+    let hard = req.query::<bool>("hard").unwrap_or(false);
+
+    // como DELETE /todos/<id> sin children: los hijos de cada borrado pasan a su padre
+    let result = store()
+        .transaction(|todos| {
+            let mut result = BatchDeleteResult::default();
+            for id in batch.ids {
+                match remove_with_children(todos, id, hard, false) {
+                    Some(removal) => {
+                        removal.publish();
+                        result.deleted.push(id);
+                    }
+                    None => result.missing.push(id),
+                }
+            }
+            result
+        })
+        .await;
+    res.render(Json(result));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(
+        ("id" = i64, Path, description = "todo id"),
+        ("upsert" = Option<bool>, Query, description = "create the todo at this id if it does not exist"),
+        ("dry_run" = Option<bool>, Query, description = "run every check and return the result with 200 without changing anything"),
+    ),
+    request_body = Todo,
+    responses(
+        (status_code = 200, description = "Replaced todo with its new ETag; with dry_run=true the todo as it would be stored. If it completed a recurring todo the body is a CompletedRecurrence", body = Todo),
+        (status_code = 201, description = "Todo created at the given id (only with upsert=true)", body = Todo),
+        (status_code = 507, description = "Creating the todo would go over MAX_TODOS (only with upsert=true)", body = ApiError),
+        (status_code = 400, description = "Invalid request body", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+        (status_code = 412, description = "If-Match does not match the current ETag", body = ApiError),
+        (status_code = 409, description = "version does not match the stored one, the body is the current todo", body = Todo),
+    )
+)]
+pub async fn update_todo(req: &mut Request, res: &mut Response) {
+    // id de los parametros
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    // extrae y parsea el cuerpo de la solicitud y se le indica que espera un obj Todo
+    if !require_json(req, res) {
+        return;
+    }
+    let mut updated_todo = match req.parse_body::<Todo>().await {
+        Ok(todo) => todo,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    tracing::debug!(todo = ?updated_todo, id = ?id, "update todo");
+
+    // el borrado solo se maneja con DELETE y /restore, no desde el cuerpo de un PUT
+    updated_todo.deleted_at = None;
+    updated_todo.normalize(*COLLAPSE_WHITESPACE);
+    if let Err(e) = updated_todo.validate() {
+        tracing::debug!(error = %e, "invalid todo");
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(e.to_string())));
+        return;
+    }
+
+    let todos = snapshot().await;
+    let current = live_todo(&todos, id);
+    if if_match_fails(etag_list(req, header::IF_MATCH).as_deref(), current) {
+        res.status_code(StatusCode::PRECONDITION_FAILED);
+        res.render(Json(ApiError::precondition_failed(id)));
+        return;
+    }
+
+    let upsert = req.query::<bool>("upsert").unwrap_or(false);
+    let dry_run = is_dry_run(req);
+    if current.is_some() || upsert {
+        if let Err(message) = check_parent(&todos, id, updated_todo.parent_id) {
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request(message)));
+            return;
+        }
+    }
+
+    if let Some(current) = current {
+        // en el cuerpo de un PUT la version 0 es que no vino
+        let Some(version) = require_version(res, Some(updated_todo.version).filter(|version| *version != 0)) else {
+            return;
+        };
+        // del cuerpo solo tomamos los campos editables: el id del path, created_at, la posicion (la maneja /move)
+        // y el archivado quedan como estaban aunque el cliente mande otra cosa
+        let mut updated_todo = current.with_fields_of(updated_todo);
+        let now = Utc::now();
+        updated_todo.track_completion(current.completed, now);
+        let recurrence = updated_todo.end_recurrence(current.completed);
+        updated_todo.version = version;
+        updated_todo.updated_at = now;
+        if dry_run {
+            preview_update(res, current, updated_todo);
+            return;
+        }
+        match store().update(updated_todo).await {
+            Ok(todo) => {
+                set_etag(res, &todo.etag());
+                events::publish(EventKind::Updated, &todo);
+                res.status_code(StatusCode::OK);
+                // devolvemos el todo como quedo, junto con la siguiente ocurrencia si completo uno recurrente
+                let next = match recurrence {
+                    Some(recurrence) => Some(create_next_occurrence(&todo, recurrence).await),
+                    None => None,
+                };
+                render_completed(res, todo, next);
+            }
+            Err(e) => render_store_error(res, e),
+        }
+        return;
+    }
+
+    // con ?upsert=true un PUT a un id que no existe crea el todo en ese id
+    if upsert {
+        // pisar un todo borrado logicamente no agrega uno nuevo al store
+        if !todos.contains_key(&id) {
+            if let Err(e) = check_capacity(todos.len(), 1) {
+                res.status_code(StatusCode::INSUFFICIENT_STORAGE);
+                res.render(Json(e));
+                return;
+            }
+        }
+        let now = Utc::now();
+        updated_todo.id = id;
+        updated_todo.completed_at = updated_todo.completed.then_some(now);
+        updated_todo.created_at = now;
+        updated_todo.updated_at = now;
+        updated_todo.position = next_position(&todos);
+        if dry_run {
+            updated_todo.version = todos.get(&id).map_or(FIRST_VERSION, |deleted| deleted.version + 1);
+            res.render(Json(updated_todo));
+            return;
+        }
+        // si el id era de un todo borrado logicamente lo pisamos, igual que antes del borrado
+        let created = match todos.get(&id) {
+            Some(deleted) => {
+                updated_todo.version = deleted.version;
+                store().update(updated_todo).await
+            }
+            None => {
+                updated_todo.version = FIRST_VERSION;
+                store().create(updated_todo).await
+            }
+        };
+        let todo = match created {
+            Ok(todo) => todo,
+            Err(e) => {
+                render_store_error(res, e);
+                return;
+            }
+        };
+        // movemos el contador para que los ids automaticos no choquen con este
+        NEXT_ID.fetch_max(id + 1, Ordering::Relaxed);
+        events::publish(EventKind::Created, &todo);
+        tracing::debug!(?id, "todo created by upsert");
+        res.status_code(StatusCode::CREATED);
+        set_location(res, id);
+        set_etag(res, &todo.etag());
+        res.render(Json(todo));
+        return;
+    }
+
+    tracing::debug!(?id, "todo is not found");
+    res.status_code(StatusCode::NOT_FOUND);
+    res.render(Json(ApiError::not_found(id)));
+
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(
+        ("id" = i64, Path, description = "todo id"),
+        ("dry_run" = Option<bool>, Query, description = "run every check and return the result with 200 without changing anything"),
+    ),
+    request_body(
+        content = TodoPatch,
+        description = "application/json: absent fields are left alone. application/merge-patch+json (RFC 7386): same, but null clears priority, due_date, parent_id, recurrence or assignee. application/json-patch+json (RFC 6902): a list of operations applied all or nothing, the version goes in a test of /version",
+    ),
+    responses(
+        (status_code = 200, description = "Updated todo, or a CompletedRecurrence if it completed a recurring todo", body = Todo),
+        (status_code = 400, description = "Invalid request body", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json, application/merge-patch+json or application/json-patch+json", body = ApiError),
+        (status_code = 422, description = "A JSON Patch operation could not be applied", body = ApiError),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+        (status_code = 412, description = "If-Match does not match the current ETag", body = ApiError),
+        (status_code = 409, description = "version does not match the stored one (the body is the current todo) or a JSON Patch test failed", body = Todo),
+    )
+)]
+pub async fn patch_todo(req: &mut Request, res: &mut Response) {
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    // a diferencia de update_todo, el cuerpo solo trae los campos que se quieren cambiar.
+    // con application/merge-patch+json se aplica RFC 7386: un null borra el campo opcional
+    let patch = if content_type_is(req, MERGE_PATCH_CONTENT_TYPE) {
+        let document = req
+            .payload()
+            .await
+            .ok()
+            .and_then(|payload| serde_json::from_slice::<serde_json::Value>(payload).ok());
+        match document {
+            Some(document) if document.is_object() => PatchBody::Merge(document),
+            _ => {
+                res.status_code(StatusCode::BAD_REQUEST);
+                res.render(Json(ApiError::bad_request("merge patch must be a JSON object")));
+                return;
+            }
+        }
+    } else if content_type_is(req, JSON_PATCH_CONTENT_TYPE) {
+        // con application/json-patch+json (RFC 6902) el cuerpo es una lista de operaciones que se aplican todas o ninguna
+        let ops = req
+            .payload()
+            .await
+            .ok()
+            .and_then(|payload| serde_json::from_slice::<json_patch::Patch>(payload).ok());
+        match ops {
+            Some(ops) => PatchBody::Json(ops),
+            None => {
+                res.status_code(StatusCode::BAD_REQUEST);
+                res.render(Json(ApiError::bad_request("json patch must be an array of operations")));
+                return;
+            }
+        }
+    } else {
+        if !require_json(req, res) {
+            return;
+        }
+        match req.parse_body::<TodoPatch>().await {
+            Ok(patch) => PatchBody::Fields(patch),
+            Err(e) => {
+                tracing::debug!(error = ?e, "invalid request body");
+                res.status_code(StatusCode::BAD_REQUEST);
+                res.render(Json(ApiError::invalid_body(&e)));
+                return;
+            }
+        }
+    };
+    tracing::debug!(?patch, ?id, "patch todo");
+
+    let todos = snapshot().await;
+    if if_match_fails(etag_list(req, header::IF_MATCH).as_deref(), live_todo(&todos, id)) {
+        res.status_code(StatusCode::PRECONDITION_FAILED);
+        res.render(Json(ApiError::precondition_failed(id)));
+        return;
+    }
+
+    if let Some(stored) = live_todo(&todos, id) {
+        let Some(version) = require_version(res, patch.version()) else {
+            return;
+        };
+        let mut todo = match patched_todo(&todos, stored, patch) {
+            Ok(todo) => todo,
+            Err((status, message)) => {
+                tracing::debug!(%message, "invalid patch");
+                res.status_code(status);
+                res.render(Json(ApiError::new(status, message)));
+                return;
+            }
+        };
+        // el update del store falla si el todo ya no esta en la version que leyo el cliente
+        let now = Utc::now();
+        todo.track_completion(stored.completed, now);
+        let recurrence = todo.end_recurrence(stored.completed);
+        todo.version = version;
+        todo.updated_at = now;
+        if is_dry_run(req) {
+            preview_update(res, stored, todo);
+            return;
+        }
+        match store().update(todo).await {
+            Ok(todo) => {
+                events::publish(EventKind::Updated, &todo);
+                set_etag(res, &todo.etag());
+                let next = match recurrence {
+                    Some(recurrence) => Some(create_next_occurrence(&todo, recurrence).await),
+                    None => None,
+                };
+                render_completed(res, todo, next);
+            }
+            Err(e) => render_store_error(res, e),
+        }
+        return;
+    }
+
+    tracing::debug!(?id, "todo is not found");
+    res.status_code(StatusCode::NOT_FOUND);
+    res.render(Json(ApiError::not_found(id)));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(("id" = i64, Path, description = "todo id")),
+    responses(
+        (status_code = 200, description = "Updated todo, or a CompletedRecurrence if it completed a recurring todo", body = Todo),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+    )
+)]
+pub async fn toggle_todo(req: &mut Request, res: &mut Response) {
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    tracing::debug!(?id, "toggle todo");
+
+    // invertimos completed sin que el cliente tenga que mandar el todo entero
+    let mut recurrence = None;
+    let toggled = modify_todo(id, |todo| {
+        (!todo.is_deleted()).then(|| {
+            todo.completed = !todo.completed;
+            recurrence = todo.end_recurrence(!todo.completed);
+            true
+        })
+    })
+    .await;
+    if let Some((todo, _)) = toggled {
+        events::publish(EventKind::Updated, &todo);
+        let next = match recurrence {
+            Some(recurrence) => Some(create_next_occurrence(&todo, recurrence).await),
+            None => None,
+        };
+        render_completed(res, todo, next);
+        return;
+    }
+
+    tracing::debug!(?id, "todo is not found");
+    res.status_code(StatusCode::NOT_FOUND);
+    res.render(Json(ApiError::not_found(id)));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(("id" = i64, Path, description = "todo id")),
+    request_body = MoveTodo,
+    responses(
+        (status_code = 200, description = "Moved todo", body = Todo),
+        (status_code = 400, description = "Invalid request body", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+    )
+)]
+pub async fn move_todo(req: &mut Request, res: &mut Response) {
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    if !require_json(req, res) {
+        return;
+    }
+    let target = match req.parse_body::<MoveTodo>().await {
+        Ok(body) => body.position,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    tracing::debug!(?id, target, "move todo");
+
+    let moved = store()
+        .transaction(|todos| {
+            live_todo(todos, id)?;
+            // la posicion pedida se recorta a los extremos
+            Some(reposition(todos, id, |order| target.min(order.len())))
+        })
+        .await;
+    let Some(todo) = moved else {
+        tracing::debug!(?id, "todo is not found");
+        res.status_code(StatusCode::NOT_FOUND);
+        res.render(Json(ApiError::not_found(id)));
+        return;
+    };
+    events::publish(EventKind::Updated, &todo);
+    res.render(Json(todo));
+}
+
+/*
+Arma el orden actual sin el todo id (los borrados no ocupan lugar), lo vuelve a meter en el indice que
+devuelve index y renumera todo de 0 a n-1 para no dejar huecos. Devuelve el todo movido; id tiene que existir.
+*/
+fn reposition(todos: &mut HashMap<i64, Todo>, id: i64, index: impl FnOnce(&[i64]) -> usize) -> Todo {
+    let mut order: Vec<i64> = todos
+        .values()
+        .filter(|todo| !todo.is_deleted())
+        .map(|todo| todo.id)
+        .collect();
+    order.sort_by_key(|id| (todos[id].position, *id));
+    order.retain(|other| *other != id);
+    let index = index(&order);
+    order.insert(index, id);
+    // los que corren de lugar tambien cambian de version, si no su ETag seguiria siendo el mismo
+    let now = Utc::now();
+    for (position, other) in order.iter().enumerate() {
+        if let Some(todo) = todos.get_mut(other) {
+            if todo.position != position && *other != id {
+                todo.position = position;
+                todo.touch(now);
+            }
+        }
+    }
+
+    let todo = todos.get_mut(&id).unwrap();
+    todo.position = index;
+    todo.touch(now);
+    todo.clone()
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(("id" = i64, Path, description = "todo id")),
+    request_body = ReorderTodo,
+    responses(
+        (status_code = 200, description = "Moved todo", body = Todo),
+        (status_code = 400, description = "Invalid request body, or the reference todo does not exist or is the same todo", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+    )
+)]
+pub async fn reorder_todo(req: &mut Request, res: &mut Response) {
+    // como /move, pero el lugar se dice respecto de otro todo, que es lo que sabe una UI de drag and drop
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    if !require_json(req, res) {
+        return;
+    }
+    let body = match req.parse_body::<ReorderTodo>().await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    // (id de referencia, si va despues de el)
+    let (reference, after) = match (body.before, body.after) {
+        (Some(before), None) => (before, false),
+        (None, Some(after)) => (after, true),
+        _ => {
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request("exactly one of before or after is required")));
+            return;
+        }
+    };
+    tracing::debug!(?id, reference, after, "reorder todo");
+
+    let moved = store()
+        .transaction(|todos| {
+            if live_todo(todos, id).is_none() {
+                tracing::debug!(?id, "todo is not found");
+                return Err(ApiError::not_found(id));
+            }
+            if reference == id {
+                return Err(ApiError::bad_request("a todo cannot be placed relative to itself"));
+            }
+            if live_todo(todos, reference).is_none() {
+                return Err(ApiError::bad_request(format!("reference todo {reference} not found")));
+            }
+            Ok(reposition(todos, id, |order| {
+                let index = order.iter().position(|other| *other == reference).unwrap();
+                if after {
+                    index + 1
+                } else {
+                    index
+                }
+            }))
+        })
+        .await;
+    match moved {
+        Ok(todo) => {
+            events::publish(EventKind::Updated, &todo);
+            res.render(Json(todo));
+        }
+        Err(e) => {
+            res.status_code(e.status());
+            res.render(Json(e));
+        }
+    }
+}
+
+/*
+Archiva todos los todos completados: siguen guardados pero dejan de aparecer en GET /todos.
+Se ven con GET /todos/archived y se vuelven a la lista con POST /todos/<id>/unarchive.
+*/
+#[endpoint(
+    tags("todos"),
+    responses((status_code = 200, description = "Number of archived todos"))
+)]
+pub async fn archive_todos(res: &mut Response) {
+    let archived = store()
+        .transaction(|todos| {
+            let now = Utc::now();
+            let mut archived = Vec::new();
+            for todo in todos.values_mut() {
+                if todo.completed && !todo.archived && !todo.is_deleted() {
+                    todo.archived = true;
+                    todo.touch(now);
+                    archived.push(todo.clone());
+                }
+            }
+            archived
+        })
+        .await;
+    for todo in &archived {
+        events::publish(EventKind::Updated, todo);
+    }
+    tracing::debug!(archived = archived.len(), "archive todos");
+    res.render(Json(json!({ "archived": archived.len() })));
+}
+
+#[endpoint(
+    tags("todos"),
+    responses((status_code = 200, description = "Archived todos, sorted by id", body = Vec<Todo>))
+)]
+pub async fn list_archived(res: &mut Response) {
+    let mut archived = store().list().await;
+    archived.retain(|todo| todo.archived && !todo.is_deleted());
+    archived.sort_by_key(|todo| todo.id);
+    res.render(Json(archived));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(("id" = i64, Path, description = "todo id")),
+    responses(
+        (status_code = 200, description = "Todo back in the active list", body = Todo),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+    )
+)]
+pub async fn unarchive_todo(req: &mut Request, res: &mut Response) {
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    tracing::debug!(?id, "unarchive todo");
+
+    let unarchived = modify_todo(id, |todo| {
+        (!todo.is_deleted()).then(|| std::mem::replace(&mut todo.archived, false))
+    })
+    .await;
+    if let Some((todo, changed)) = unarchived {
+        if changed {
+            events::publish(EventKind::Updated, &todo);
+        }
+        res.render(Json(todo));
+        return;
+    }
+
+    tracing::debug!(?id, "todo is not found");
+    res.status_code(StatusCode::NOT_FOUND);
+    res.render(Json(ApiError::not_found(id)));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(("id" = i64, Path, description = "todo id")),
+    responses(
+        (status_code = 200, description = "Direct children of the todo, sorted by id", body = Vec<Todo>),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+    )
+)]
+pub async fn list_children(req: &mut Request, res: &mut Response) {
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    let mut children = store().list().await;
+
+    if !children.iter().any(|todo| todo.id == id && !todo.is_deleted()) {
+        tracing::debug!(?id, "todo is not found");
+        res.status_code(StatusCode::NOT_FOUND);
+        res.render(Json(ApiError::not_found(id)));
+        return;
+    }
+    children.retain(|todo| !todo.is_deleted() && todo.parent_id == Some(id));
+    children.sort_by_key(|todo| todo.id);
+    res.render(Json(children));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(("id" = i64, Path, description = "todo id")),
+    responses(
+        (status_code = 200, description = "Restored todo", body = Todo),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+    )
+)]
+pub async fn restore_todo(req: &mut Request, res: &mut Response) {
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    tracing::debug!(?id, "restore todo");
+
+    // restaurar un todo que no estaba borrado no cambia nada y devuelve el todo igual
+    if let Some((todo, restored)) = modify_todo(id, |todo| Some(todo.deleted_at.take().is_some())).await {
+        if restored {
+            events::publish(EventKind::Updated, &todo);
+        }
+        res.render(Json(todo));
+        return;
+    }
+
+    tracing::debug!(?id, "todo is not found");
+    res.status_code(StatusCode::NOT_FOUND);
+    res.render(Json(ApiError::not_found(id)));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(
+        ("id" = i64, Path, description = "id of the todo to copy"),
+        ("suffix" = Option<bool>, Query, description = "append \" (copy)\" to the text of the new todo"),
+    ),
+    responses(
+        (status_code = 201, description = "New todo copied from the given one", body = Todo),
+        (status_code = 400, description = "The copied text is too long", body = ApiError),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+        (status_code = 507, description = "The store already holds MAX_TODOS todos", body = ApiError),
+    )
+)]
+pub async fn duplicate_todo(req: &mut Request, res: &mut Response) {
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    let suffix = req.query::<bool>("suffix").unwrap_or(false);
+    tracing::debug!(?id, suffix, "duplicate todo");
+
+    let _create = CREATE_LOCK.lock().await;
+    let todos = snapshot().await;
+    let Some(source) = live_todo(&todos, id) else {
+        tracing::debug!(?id, "todo is not found");
+        res.status_code(StatusCode::NOT_FOUND);
+        res.render(Json(ApiError::not_found(id)));
+        return;
+    };
+    if let Err(e) = check_capacity(todos.len(), 1) {
+        res.status_code(StatusCode::INSUFFICIENT_STORAGE);
+        res.render(Json(e));
+        return;
+    }
+
+    // la copia sirve de plantilla: mismos datos pero pendiente, con otro id y al final del orden
+    let text = if suffix {
+        format!("{} (copy)", source.text)
+    } else {
+        source.text.clone()
+    };
+    let mut todo = NewTodo {
+        text,
+        completed: false,
+        priority: source.priority,
+        tags: source.tags.clone(),
+        due_date: source.due_date,
+        parent_id: source.parent_id,
+        recurrence: source.recurrence.clone(),
+        assignee: source.assignee.clone(),
+    }
+    .into_todo(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    if let Err(e) = todo.validate() {
+        tracing::debug!(error = %e, "invalid todo");
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(e.to_string())));
+        return;
+    }
+    todo.position = next_position(&todos);
+    let todo = match store().create(todo).await {
+        Ok(todo) => todo,
+        Err(e) => {
+            render_store_error(res, e);
+            return;
+        }
+    };
+    events::publish(EventKind::Created, &todo);
+    res.status_code(StatusCode::CREATED);
+    set_location(res, todo.id);
+    res.render(Json(todo));
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(
+        ("id" = i64, Path, description = "todo id"),
+        ("hard" = Option<bool>, Query, description = "remove the todo for good instead of marking it deleted"),
+        ("children" = Option<String>, Query, description = "reparent (default) moves the children to this todo's parent, cascade deletes them too"),
+        ("dry_run" = Option<bool>, Query, description = "run every check and return the result with 200 without changing anything"),
+        ("return" = Option<bool>, Query, description = "answer 200 with the deleted todo instead of 204"),
+    ),
+    responses(
+        (status_code = 204, description = "Todo deleted"),
+        (status_code = 200, description = "The deleted todo with return=true, or the todos that would be deleted with dry_run=true", body = Vec<Todo>),
+        (status_code = 404, description = "Todo not found", body = ApiError),
+        (status_code = 412, description = "If-Match does not match the current ETag", body = ApiError),
+    )
+)]
+pub async fn delete_todo(req: &mut Request, res: &mut Response) {
+    // id de parametros
+    let Some(id) = path_id(req, res) else {
+        return;
+    };
+    // mensaje de depuracion
+    tracing::debug!(?id, "delete todo");
+
+    // por defecto el borrado es logico (se puede deshacer con /restore); ?hard=true lo saca del store,
+    // tambien si ya estaba borrado
+    let hard = req.query::<bool>("hard").unwrap_or(false);
+    // que pasa con los hijos del todo: por defecto pasan a colgar del padre del borrado
+    let cascade = match req.query::<String>("children").as_deref() {
+        None | Some("reparent") => false,
+        Some("cascade") => true,
+        Some(other) => {
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::bad_request(format!(
+                "invalid children {other:?}, expected reparent or cascade"
+            ))));
+            return;
+        }
+    };
+
+    // el If-Match se compara con el todo como esta guardado; un hard delete tambien acepta uno ya borrado
+    let if_match = etag_list(req, header::IF_MATCH);
+    let remove = |todos: &mut HashMap<i64, Todo>| {
+        let current = if hard { todos.get(&id) } else { live_todo(todos, id) };
+        if if_match_fails(if_match.as_deref(), current) {
+            return Err(ApiError::precondition_failed(id));
+        }
+        // devuelve None si no existia, asi sabemos si se elimino o no y despues devolvemos un status code
+        remove_with_children(todos, id, hard, cascade).ok_or_else(|| {
+            tracing::debug!(?id, "todo is not found");
+            ApiError::not_found(id)
+        })
+    };
+
+    // el dry run borra sobre una copia y devuelve lo que se borraria (con los hijos en cascada)
+    if is_dry_run(req) {
+        match remove(&mut snapshot().await) {
+            Ok(removal) => res.render(Json(removal.removed)),
+            Err(e) => {
+                res.status_code(e.status());
+                res.render(Json(e));
+            }
+        }
+        return;
+    }
+
+    match store().transaction(remove).await {
+        Ok(removal) => {
+            removal.publish();
+            // con ?return=true devolvemos el todo borrado (sin los hijos de un cascade), asi el cliente puede
+            // ofrecer deshacer; por defecto 204 sin cuerpo
+            if req.query::<bool>("return").unwrap_or(false) {
+                res.render(Json(&removal.removed[0]));
+            } else {
+                res.status_code(StatusCode::NO_CONTENT);
+            }
+        }
+        Err(e) => {
+            res.status_code(e.status());
+            res.render(Json(e));
+        }
+    }
+}
+
+#[endpoint(
+    tags("todos"),
+    parameters(("confirm" = bool, Query, description = "must be true to delete everything")),
+    responses(
+        (status_code = 200, description = "Number of deleted todos, not counting the ones already soft-deleted"),
+        (status_code = 400, description = "Missing confirm=true", body = ApiError),
+    )
+)]
+pub async fn clear_todos(req: &mut Request, res: &mut Response) {
+    // para evitar borrar todo por accidente exigimos ?confirm=true
+    if !req.query::<bool>("confirm").unwrap_or(false) {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(
+            "refusing to delete all todos without ?confirm=true",
+        )));
+        return;
+    }
+
+    // los borrados logicamente tambien se purgan, pero para el cliente ya no existian: no cuentan ni se avisan
+    let removed: Vec<Todo> = store()
+        .transaction(|todos| todos.drain().map(|(_, todo)| todo).filter(|todo| !todo.is_deleted()).collect())
+        .await;
+    let deleted = removed.len();
+    for todo in &removed {
+        events::publish(EventKind::Deleted, todo);
+    }
+    tracing::debug!(deleted, "clear todos");
+    res.render(Json(json!({ "deleted": deleted })));
+}
+
+/*
+Backup completo del store: todos los todos con todos sus campos, tambien los borrados logicamente y los archivados,
+en el formato que acepta POST /todos/import. Se descarga como archivo, igual que el CSV de GET /todos.
+*/
+#[endpoint(
+    tags("todos"),
+    responses((status_code = 200, description = "Every todo in the store, sorted by id", body = TodoExport))
+)]
+pub async fn export_todos(res: &mut Response) {
+    let mut todos = store().list().await;
+    todos.sort_by_key(|todo| todo.id);
+    res.add_header(header::CONTENT_DISPOSITION, "attachment; filename=\"todos.json\"", true).ok();
+    res.render(Json(TodoExport {
+        format: EXPORT_FORMAT,
+        exported_at: Utc::now(),
+        todos,
+    }));
+}
+
+/*
+Carga un documento de GET /todos/export. Por defecto reemplaza el store entero; con ?merge=true agrega los todos
+a los que ya hay y los ids que ya existen quedan como estaban (conflicted). Los todos se guardan tal cual vienen
+(ids, fechas, version, posicion), solo normalizados. Se saltean (skipped) los que no pasan validate, los ids
+repetidos en el documento (queda el primero) y los que tienen un parent que no queda en el store o arma un ciclo.
+El documento puede pesar hasta MAX_BULK_BODY_SIZE, como los cuerpos de /todos/batch.
+*/
+#[endpoint(
+    tags("todos"),
+    parameters(("merge" = Option<bool>, Query, description = "add to the current todos instead of replacing them")),
+    request_body = TodoExport,
+    responses(
+        (status_code = 200, description = "Which ids were imported, skipped or conflicted", body = ImportResult),
+        (status_code = 400, description = "Invalid request body or unknown format", body = ApiError),
+        (status_code = 415, description = "Content-Type is not application/json", body = ApiError),
+        (status_code = 507, description = "The import does not fit under MAX_TODOS", body = ApiError),
+    )
+)]
+pub async fn import_todos(req: &mut Request, res: &mut Response) {
+    if !require_json(req, res) {
+        return;
+    }
+    let document = match req.parse_body::<TodoExport>().await {
+        Ok(document) => document,
+        Err(e) => {
+            tracing::debug!(error = ?e, "invalid request body");
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(ApiError::invalid_body(&e)));
+            return;
+        }
+    };
+    if document.format != EXPORT_FORMAT {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(format!(
+            "unsupported export format {}, expected {EXPORT_FORMAT}",
+            document.format
+        ))));
+        return;
+    }
+    let merge = req.query::<bool>("merge").unwrap_or(false);
+    tracing::debug!(count = document.todos.len(), merge, "import todos");
+
+    let imported: Result<_, ApiError> = store()
+        .transaction(|todos| {
+            let mut result = ImportResult::default();
+            let mut incoming: HashMap<i64, Todo> = HashMap::new();
+            for mut todo in document.todos {
+                todo.normalize(*COLLAPSE_WHITESPACE);
+                // un export sin version llega con 0, que ningun PUT puede mandar
+                todo.version = todo.version.max(FIRST_VERSION);
+                if todo.validate().is_err() || incoming.contains_key(&todo.id) {
+                    result.skipped.push(todo.id);
+                } else if merge && todos.contains_key(&todo.id) {
+                    result.conflicted.push(todo.id);
+                } else {
+                    incoming.insert(todo.id, todo);
+                }
+            }
+
+            // sacar un todo por su parent puede dejar sin parent a sus hijos, asi que repetimos hasta que no cambie nada
+            loop {
+                let parent_of = |id: i64| {
+                    incoming
+                        .get(&id)
+                        .or_else(|| todos.get(&id).filter(|_| merge))
+                        .map(|todo| todo.parent_id)
+                };
+                let orphans: Vec<i64> = incoming
+                    .values()
+                    .filter(|todo| check_parent_with(parent_of, todo.id, todo.parent_id).is_err())
+                    .map(|todo| todo.id)
+                    .collect();
+                if orphans.is_empty() {
+                    break;
+                }
+                for id in orphans {
+                    incoming.remove(&id);
+                    result.skipped.push(id);
+                }
+            }
+
+            let stored = if merge { todos.len() } else { 0 };
+            check_capacity(stored, incoming.len())?;
+
+            let removed: Vec<Todo> = if merge {
+                Vec::new()
+            } else {
+                todos.drain().map(|(_, todo)| todo).collect()
+            };
+            let mut imported: Vec<Todo> = incoming.into_values().collect();
+            imported.sort_by_key(|todo| todo.id);
+            todos.extend(imported.iter().map(|todo| (todo.id, todo.clone())));
+            Ok((result, removed, imported))
+        })
+        .await;
+    let (mut result, removed, imported) = match imported {
+        Ok(imported) => imported,
+        Err(e) => {
+            res.status_code(e.status());
+            res.render(Json(e));
+            return;
+        }
+    };
+    // movemos el contador para que los ids automaticos no choquen con los importados
+    if let Some(max_id) = imported.last().map(|todo| todo.id) {
+        NEXT_ID.fetch_max(max_id + 1, Ordering::Relaxed);
+    }
+    for todo in &removed {
+        events::publish(EventKind::Deleted, todo);
+    }
+    for todo in &imported {
+        events::publish(EventKind::Created, todo);
+    }
+    result.imported = imported.iter().map(|todo| todo.id).collect();
+    result.skipped.sort_unstable();
+    result.conflicted.sort_unstable();
+    res.render(Json(result));
+}
+
+// datos de ejemplo de /todos/seed, siempre los mismos para que los tests y las demos sepan que esperar
+fn sample_todos() -> Vec<Todo> {
+    let samples = [
+        ("buy milk", false, Some(2), vec!["home"], None),
+        ("write quarterly report", false, Some(5), vec!["work"], None),
+        ("collect the figures for the report", true, Some(4), vec!["work"], Some(2)),
+        ("call the dentist", false, None, vec!["health"], None),
+        ("water the plants", true, Some(1), vec!["home"], None),
+    ];
+    samples
+        .into_iter()
+        .enumerate()
+        .map(|(index, (text, completed, priority, tags, parent_id))| {
+            let mut todo = NewTodo {
+                text: text.to_string(),
+                completed,
+                priority,
+                tags: tags.into_iter().map(String::from).collect(),
+                due_date: None,
+                parent_id,
+                recurrence: None,
+                assignee: None,
+            }
+            .into_todo(index as i64 + 1);
+            todo.position = index;
+            todo
+        })
+        .collect()
+}
+
+/*
+Solo para desarrollo: vacia el store y carga sample_todos() con ids desde 1. Sin DEV_MODE=true responde 404,
+como si la ruta no existiera. DEV_MODE se lee en cada request para poder prenderlo y apagarlo en los tests.
+*/
+#[endpoint(
+    tags("todos"),
+    responses(
+        (status_code = 201, description = "Store replaced by the sample todos", body = Vec<Todo>),
+        (status_code = 404, description = "DEV_MODE is not enabled", body = ApiError),
+    )
+)]
+pub async fn seed_todos(res: &mut Response) {
+    if !std::env::var("DEV_MODE").is_ok_and(|value| value == "true") {
+        res.status_code(StatusCode::NOT_FOUND);
+        res.render(Json(ApiError::new(StatusCode::NOT_FOUND, "not found")));
+        return;
+    }
+
+    let seeded = sample_todos();
+    let removed: Vec<Todo> = store()
+        .transaction(|todos| {
+            let removed = todos.drain().map(|(_, todo)| todo).collect();
+            todos.extend(seeded.iter().map(|todo| (todo.id, todo.clone())));
+            removed
+        })
+        .await;
+    // los ids automaticos siguen despues de los de ejemplo. en modo multi-tenant el contador es de todos
+    // los usuarios, asi que solo lo movemos hacia adelante
+    let next_id = seeded.len() as i64 + 1;
+    if tenant::current().is_some() {
+        NEXT_ID.fetch_max(next_id, Ordering::Relaxed);
+    } else {
+        NEXT_ID.store(next_id, Ordering::Relaxed);
+    }
+    for todo in &removed {
+        events::publish(EventKind::Deleted, todo);
+    }
+    for todo in &seeded {
+        events::publish(EventKind::Created, todo);
+    }
+    tracing::info!(removed = removed.len(), seeded = seeded.len(), "store seeded");
+    res.status_code(StatusCode::CREATED);
+    res.render(Json(seeded));
+}
+
+/*
+Metricas para Prometheus, ver el modulo metrics para los nombres.
+*/
+#[endpoint(
+    tags("health"),
+    responses((status_code = 200, description = "Metrics in Prometheus text format"))
+)]
+pub async fn get_metrics(res: &mut Response) {
+    let todos = store().list().await.iter().filter(|todo| !todo.is_deleted()).count();
+    res.add_header(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8", true).ok();
+    res.write_body(metrics::render(todos)).ok();
+}
+
+/*
+El servidor esta vivo si puede responder. Ademas cuenta hace cuanto arranco y cuantos todos hay en el store
+(sin los borrados logicamente, como /todos/count y /metrics), leidos con el read lock, para ver el estado de
+un vistazo sin /metrics.
+*/
+#[endpoint(
+    tags("health"),
+    responses((status_code = 200, description = "Service is up, with its uptime in seconds and the number of stored todos"))
+)]
+pub async fn health(res: &mut Response) {
+    let todo_count = STORE.list().await.iter().filter(|todo| !todo.is_deleted()).count();
+    res.render(Json(json!({
+        "status": "ok",
+        "uptime_secs": STARTED_AT.elapsed().as_secs(),
+        "todo_count": todo_count,
+    })));
+}
+
+/*
+El servidor esta listo cuando el store ya se cargo desde disco. Lazy::get no fuerza
+la inicializacion ni toma el lock, asi que es barato de llamar seguido.
+*/
+#[endpoint(
+    tags("health"),
+    responses(
+        (status_code = 200, description = "Store loaded, ready to serve"),
+        (status_code = 503, description = "Store not loaded yet"),
+    )
+)]
+pub async fn ready(res: &mut Response) {
+    if Lazy::get(&STORE).is_some() {
+        res.render(Json(json!({ "status": "ready" })));
+    } else {
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+        res.render(Json(json!({ "status": "loading" })));
+    }
+}
+
+pub mod models {
+    /* 
+    use serde::{Serialize, Deserialize};: Importa los traits Serialize y Deserialize del paquete serde. Estos traits son utilizados
+    para serializar y deserializar estructuras de datos en formatos como JSON.
+    */
+    use std::collections::BTreeMap;
+
+    use std::fmt;
+
+    use async_graphql::SimpleObject;
+    use chrono::{DateTime, Duration, Months, Utc};
+    use salvo::oapi::{ToParameters, ToSchema};
+    use serde::{Serialize, Deserialize};
+    /*
+    created_at y updated_at los maneja el servidor. Tienen default para poder
+    leer cuerpos y archivos viejos que no los traen.
+     */
+    #[derive(Serialize, Deserialize, ToSchema, SimpleObject, Clone, Debug)]
+    pub struct Todo {
+        pub id: i64, 
+        pub text: String,
+        pub completed: bool,
+        // cuando se marco como completed; lo maneja el servidor y vuelve a None si el todo pasa a pendiente
+        #[serde(default)]
+        pub completed_at: Option<DateTime<Utc>>,
+        #[serde(default = "Utc::now")]
+        pub created_at: DateTime<Utc>,
+        #[serde(default = "Utc::now")]
+        pub updated_at: DateTime<Utc>,
+        // prioridad opcional de MIN_PRIORITY a MAX_PRIORITY, los clientes viejos no la mandan
+        #[serde(default)]
+        pub priority: Option<u8>,
+        // etiquetas para organizar los todos, siempre guardadas en minusculas
+        #[serde(default)]
+        pub tags: Vec<String>,
+        // fecha limite opcional en RFC 3339, por ejemplo "2024-05-01T12:00:00Z"
+        #[serde(default)]
+        pub due_date: Option<DateTime<Utc>>,
+        // lugar en el orden manual de los todos, lo cambia POST /todos/<id>/move
+        #[serde(default)]
+        pub position: usize,
+        // cuando se borro el todo; mientras tenga valor no aparece en list ni en get
+        #[serde(default)]
+        pub deleted_at: Option<DateTime<Utc>>,
+        // todo del que este es una subtarea; GET /todos/<id>/children lista los hijos
+        #[serde(default)]
+        pub parent_id: Option<i64>,
+        // los archivados (con POST /todos/archive) no aparecen en GET /todos
+        #[serde(default)]
+        pub archived: bool,
+        // uno de RECURRENCES; al completar el todo se crea la siguiente ocurrencia, ver next_occurrence
+        #[serde(default)]
+        pub recurrence: Option<String>,
+        // a quien esta asignado, para listas compartidas; se guarda sin espacios alrededor y en minusculas
+        #[serde(default)]
+        pub assignee: Option<String>,
+        /*
+        Version para control de concurrencia optimista: arranca en FIRST_VERSION y sube con cada cambio.
+        PUT y PATCH tienen que mandar la version que leyo el cliente. En un cuerpo, 0 es que no vino.
+         */
+        #[serde(default)]
+        pub version: u64,
+    }
+
+    // nombres de los campos de un Todo en JSON, los que se pueden pedir con ?fields=
+    pub const TODO_FIELDS: [&str; 16] = [
+        "id", "text", "completed", "completed_at", "created_at", "updated_at", "priority", "tags", "due_date",
+        "position", "deleted_at", "parent_id", "archived", "recurrence", "assignee", "version",
+    ];
+
+    /*
+    Parsea ?fields=id,text: nombres separados por coma, sin importar los espacios. Un nombre que no es un campo
+    de Todo es un error en vez de ignorarse, asi un typo no devuelve objetos vacios sin que el cliente se entere.
+     */
+    pub fn parse_fields(fields: &str) -> Result<Vec<&str>, String> {
+        let fields: Vec<&str> = fields.split(',').map(str::trim).filter(|field| !field.is_empty()).collect();
+        if fields.is_empty() {
+            return Err("fields must name at least one field".to_string());
+        }
+        if let Some(unknown) = fields.iter().find(|field| !TODO_FIELDS.contains(field)) {
+            return Err(format!("unknown field {unknown:?}, expected one of {}", TODO_FIELDS.join(", ")));
+        }
+        Ok(fields)
+    }
+
+    // version de un todo recien creado
+    pub const FIRST_VERSION: u64 = 1;
+
+    // largo maximo, en caracteres, del text de un todo
+    pub const MAX_TEXT_LEN: usize = 500;
+
+    // rango valido para priority
+    pub const MIN_PRIORITY: u8 = 1;
+    pub const MAX_PRIORITY: u8 = 5;
+
+    // un tag como se guarda: sin espacios alrededor y en minusculas; None si queda vacio
+    pub fn normalize_tag(tag: &str) -> Option<String> {
+        let tag = tag.trim().to_lowercase();
+        (!tag.is_empty()).then_some(tag)
+    }
+
+    // assignee sin espacios alrededor y en minusculas, asi "Alice" y " alice" son la misma persona; vacio es nadie
+    pub fn normalize_assignee(assignee: &str) -> Option<String> {
+        let assignee = assignee.trim().to_lowercase();
+        (!assignee.is_empty()).then_some(assignee)
+    }
+
+    // text tal cual si case_sensitive, si no en minusculas, para comparar textos
+    pub fn fold_case(text: &str, case_sensitive: bool) -> String {
+        if case_sensitive {
+            text.to_string()
+        } else {
+            text.to_lowercase()
+        }
+    }
+
+    // valores validos para recurrence
+    pub const RECURRENCES: [&str; 3] = ["daily", "weekly", "monthly"];
+
+    // date corrida un periodo de recurrence (que ya paso por validate)
+    fn advance(date: DateTime<Utc>, recurrence: &str) -> DateTime<Utc> {
+        match recurrence {
+            "daily" => date + Duration::days(1),
+            "weekly" => date + Duration::weeks(1),
+            _ => date.checked_add_months(Months::new(1)).unwrap_or(DateTime::<Utc>::MAX_UTC),
+        }
+    }
+
+    impl Todo {
+        // el todo en JSON con solo los campos de fields (ya validados con parse_fields), en el orden de siempre
+        pub fn select_fields(&self, fields: &[&str]) -> serde_json::Value {
+            let mut value = serde_json::to_value(self).expect("a todo always serializes");
+            if let serde_json::Value::Object(map) = &mut value {
+                map.retain(|key, _| fields.contains(&key.as_str()));
+            }
+            value
+        }
+
+        /*
+        Normaliza el todo antes de guardarlo: el text queda sin espacios alrededor (y con collapse_whitespace
+        cada tira de espacios internos pasa a ser uno solo) y los tags pasan por normalize_tags. Por eso lo que
+        se guarda y se devuelve puede no ser exactamente lo que mando el cliente. Un text que solo tenia
+        espacios queda vacio y validate lo rechaza.
+         */
+        pub fn normalize(&mut self, collapse_whitespace: bool) {
+            self.text = if collapse_whitespace {
+                self.text.split_whitespace().collect::<Vec<_>>().join(" ")
+            } else {
+                self.text.trim().to_string()
+            };
+            self.normalize_tags();
+            self.recurrence = self.recurrence.as_deref().map(|recurrence| recurrence.trim().to_lowercase());
+            self.assignee = self.assignee.as_deref().and_then(normalize_assignee);
+        }
+
+        /*
+        Deja los tags sin espacios alrededor, en minusculas, sin vacios y sin repetidos,
+        asi el filtro ?tag= no depende de como los escribio el cliente.
+         */
+        pub fn normalize_tags(&mut self) {
+            let mut tags: Vec<String> = self.tags.iter().filter_map(|tag| normalize_tag(tag)).collect();
+            tags.sort();
+            tags.dedup();
+            self.tags = tags;
+        }
+
+        /*
+        Verifica las reglas que tiene que cumplir un todo antes de guardarse:
+        el text no puede estar vacio ni superar MAX_TEXT_LEN caracteres,
+        si tiene priority tiene que estar entre MIN_PRIORITY y MAX_PRIORITY
+        y si tiene recurrence tiene que ser una de RECURRENCES.
+         */
+        pub fn validate(&self) -> Result<(), ValidationError> {
+            if self.text.is_empty() {
+                return Err(ValidationError::EmptyText);
+            }
+            let len = self.text.chars().count();
+            if len > MAX_TEXT_LEN {
+                return Err(ValidationError::TextTooLong { len, max: MAX_TEXT_LEN });
+            }
+            if let Some(priority) = self.priority {
+                if !(MIN_PRIORITY..=MAX_PRIORITY).contains(&priority) {
+                    return Err(ValidationError::InvalidPriority(priority));
+                }
+            }
+            if let Some(recurrence) = &self.recurrence {
+                if !RECURRENCES.contains(&recurrence.as_str()) {
+                    return Err(ValidationError::InvalidRecurrence(recurrence.clone()));
+                }
+            }
+            Ok(())
+        }
+
+        /*
+        ETag del todo: "id-version", entre comillas como pide HTTP. Todo cambio sube la version (ver touch),
+        asi que cambia con cualquier campo, es estable entre reinicios y el cliente lo puede armar con lo que ya tiene.
+         */
+        pub fn etag(&self) -> String {
+            format!("\"{}-{}\"", self.id, self.version)
+        }
+
+        // text sin espacios de mas (y en minusculas si no es case_sensitive), para comparar todos por contenido
+        pub fn normalized_text(&self, case_sensitive: bool) -> String {
+            fold_case(&self.text.split_whitespace().collect::<Vec<_>>().join(" "), case_sensitive)
+        }
+
+        /*
+        Devuelve una copia del todo con el merge patch aplicado. Solo se pueden cambiar los mismos campos
+        que con TodoPatch, ver with_fields_of.
+         */
+        pub fn merge_patch(&self, document: &serde_json::Value) -> Result<Todo, String> {
+            let mut value = serde_json::to_value(self).map_err(|e| e.to_string())?;
+            json_merge(&mut value, document);
+            let merged: Todo =
+                serde_json::from_value(value).map_err(|e| format!("invalid merge patch: {e}"))?;
+            Ok(self.with_fields_of(merged))
+        }
+
+        /*
+        Igual que merge_patch pero con las operaciones de JSON Patch (RFC 6902), que se aplican todas o ninguna.
+        El error de afuera es la operacion que fallo; el de adentro, que el resultado no es un todo.
+         */
+        pub fn json_patch(&self, ops: &json_patch::Patch) -> Result<Result<Todo, String>, json_patch::PatchError> {
+            let mut value = match serde_json::to_value(self) {
+                Ok(value) => value,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+            json_patch::patch(&mut value, ops)?;
+            Ok(serde_json::from_value(value)
+                .map(|patched| self.with_fields_of(patched))
+                .map_err(|e| format!("invalid json patch result: {e}")))
+        }
+
+        /*
+        Copia de este todo con los campos que puede editar un cliente (text, completed, priority, due_date,
+        parent_id, tags, recurrence y assignee) tomados de other. Lo demas (id, fechas, version, posicion, borrado y archivo) no cambia.
+         */
+        pub fn with_fields_of(&self, other: Todo) -> Todo {
+            Todo {
+                text: other.text,
+                completed: other.completed,
+                priority: other.priority,
+                due_date: other.due_date,
+                parent_id: other.parent_id,
+                tags: other.tags,
+                recurrence: other.recurrence,
+                assignee: other.assignee,
+                ..self.clone()
+            }
+        }
+
+        /*
+        Relevancia del todo para la busqueda q, sin importar mayusculas salvo con case_sensitive; 0 si no coincide. Cada palabra de q
+        suma segun su mejor coincidencia con las palabras del text: 3 si es la palabra entera, 2 si es el
+        comienzo de una palabra y 1 si aparece en el medio. Un tag igual a la palabra suma 2, y si el text
+        entero empieza con q se suman 3 mas.
+         */
+        pub fn search_score(&self, q: &str, case_sensitive: bool) -> u32 {
+            let q = fold_case(q, case_sensitive);
+            let text = fold_case(&self.text, case_sensitive);
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let mut score = 0;
+            for term in q.split_whitespace() {
+                score += words
+                    .iter()
+                    .map(|word| match word {
+                        word if *word == term => 3,
+                        word if word.starts_with(term) => 2,
+                        word if word.contains(term) => 1,
+                        _ => 0,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                // los tags estan en minusculas, asi que se comparan siempre sin mayusculas
+                if self.tags.iter().any(|tag| *tag == term.to_lowercase()) {
+                    score += 2;
+                }
+            }
+            if score > 0 && text.starts_with(q.trim()) {
+                score += 3;
+            }
+            score
+        }
+
+        // completed_at sigue al cambio de completed: now si se acaba de terminar, None si volvio a pendiente
+        pub fn track_completion(&mut self, was_completed: bool, now: DateTime<Utc>) {
+            match (was_completed, self.completed) {
+                (false, true) => self.completed_at = Some(now),
+                (true, false) => self.completed_at = None,
+                _ => {}
+            }
+        }
+
+        /*
+        Si el cambio acaba de completar un todo recurrente le saca la recurrencia y la devuelve: la recurrencia
+        pasa a la siguiente ocurrencia, asi descompletar y volver a completar este todo no crea otra mas.
+         */
+        pub fn end_recurrence(&mut self, was_completed: bool) -> Option<String> {
+            if was_completed || !self.completed {
+                return None;
+            }
+            self.recurrence.take()
+        }
+
+        /*
+        Siguiente ocurrencia de este todo, que se acaba de completar: los mismos datos, pendiente, con la recurrencia
+        y la due_date corrida un periodo (de ahora si no tenia). Si la due_date ya habia quedado atras se siguen
+        sumando periodos hasta pasar now, las ocurrencias salteadas no se crean. La position la pone quien la guarda.
+         */
+        pub fn next_occurrence(&self, id: i64, recurrence: String, now: DateTime<Utc>) -> Todo {
+            let mut due_date = advance(self.due_date.unwrap_or(now), &recurrence);
+            while due_date <= now {
+                due_date = advance(due_date, &recurrence);
+            }
+            let mut next = NewTodo {
+                text: self.text.clone(),
+                completed: false,
+                priority: self.priority,
+                tags: self.tags.clone(),
+                due_date: Some(due_date),
+                parent_id: self.parent_id,
+                recurrence: Some(recurrence),
+                assignee: self.assignee.clone(),
+            }
+            .into_todo(id);
+            next.created_at = now;
+            next.updated_at = now;
+            next
+        }
+
+        // registra un cambio: actualiza updated_at y sube la version
+        pub fn touch(&mut self, now: DateTime<Utc>) {
+            self.updated_at = now;
+            self.version += 1;
+        }
+
+        pub fn is_deleted(&self) -> bool {
+            self.deleted_at.is_some()
+        }
+
+        // un todo esta vencido si sigue pendiente y su due_date ya paso; sin due_date nunca vence
+        pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
+            !self.completed && self.due_date.is_some_and(|due_date| due_date < now)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ValidationError {
+        EmptyText,
+        TextTooLong { len: usize, max: usize },
+        InvalidPriority(u8),
+        InvalidRecurrence(String),
+    }
+
+    impl fmt::Display for ValidationError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ValidationError::EmptyText => write!(f, "text must not be empty"),
+                ValidationError::TextTooLong { len, max } => {
+                    write!(f, "text must be at most {max} characters, got {len}")
+                }
+                ValidationError::InvalidPriority(priority) => write!(
+                    f,
+                    "priority must be between {MIN_PRIORITY} and {MAX_PRIORITY}, got {priority}"
+                ),
+                ValidationError::InvalidRecurrence(recurrence) => write!(
+                    f,
+                    "recurrence must be one of {}, got {recurrence:?}",
+                    RECURRENCES.join(", ")
+                ),
+            }
+        }
+    }
+
+    /*
+    NewTodo es el cuerpo que se recibe al crear un todo: igual que Todo pero sin id,
+    ya que el id lo asigna el servidor. Si no se manda completed se asume false.
+     */
+    #[derive(Deserialize, ToSchema, Debug)]
+    pub struct NewTodo {
+        // se guarda sin los espacios de alrededor, ver Todo::normalize
+        pub text: String,
+        #[serde(default)]
+        pub completed: bool,
+        #[serde(default)]
+        pub priority: Option<u8>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        pub due_date: Option<DateTime<Utc>>,
+        #[serde(default)]
+        pub parent_id: Option<i64>,
+        #[serde(default)]
+        pub recurrence: Option<String>,
+        #[serde(default)]
+        pub assignee: Option<String>,
+    }
+
+    /*
+    Un todo de POST /todos/batch: un NewTodo con el id que elige el cliente. Los campos que maneja el servidor
+    (fechas, version, borrado, archivo) no se aceptan, se arman como en create_todo.
+     */
+    #[derive(Deserialize, ToSchema, Debug)]
+    pub struct BatchTodo {
+        pub id: i64,
+        #[serde(flatten)]
+        pub todo: NewTodo,
+    }
+
+    impl NewTodo {
+        pub fn into_todo(self, id: i64) -> Todo {
+            let now = Utc::now();
+            Todo {
+                id,
+                text: self.text,
+                completed: self.completed,
+                completed_at: self.completed.then_some(now),
+                created_at: now,
+                updated_at: now,
+                priority: self.priority,
+                tags: self.tags,
+                due_date: self.due_date,
+                position: 0,
+                deleted_at: None,
+                parent_id: self.parent_id,
+                archived: false,
+                recurrence: self.recurrence,
+                assignee: self.assignee,
+                version: FIRST_VERSION,
+            }
+        }
+    }
+
+    /*
+    TodoPatch representa una actualizacion parcial: cada campo es opcional
+    y solo los que vienen en el cuerpo se aplican sobre el Todo guardado.
+     */
+    #[derive(Deserialize, ToSchema, Debug, Default)]
+    pub struct TodoPatch {
+        // version que leyo el cliente, obligatoria
+        pub version: Option<u64>,
+        pub text: Option<String>,
+        pub completed: Option<bool>,
+        pub priority: Option<u8>,
+        pub due_date: Option<DateTime<Utc>>,
+        pub parent_id: Option<i64>,
+        // si viene, reemplaza la lista completa de tags
+        pub tags: Option<Vec<String>>,
+        pub recurrence: Option<String>,
+        pub assignee: Option<String>,
+    }
+
+    impl TodoPatch {
+        // solo pisa los campos que vinieron en el cuerpo, el resto queda igual
+        pub fn apply(self, todo: &mut Todo) {
+            if let Some(text) = self.text {
+                todo.text = text;
+            }
+            if let Some(completed) = self.completed {
+                todo.completed = completed;
+            }
+            if let Some(priority) = self.priority {
+                todo.priority = Some(priority);
+            }
+            if let Some(due_date) = self.due_date {
+                todo.due_date = Some(due_date);
+            }
+            if let Some(parent_id) = self.parent_id {
+                todo.parent_id = Some(parent_id);
+            }
+            if let Some(tags) = self.tags {
+                todo.tags = tags;
+            }
+            if let Some(recurrence) = self.recurrence {
+                todo.recurrence = Some(recurrence);
+            }
+            if let Some(assignee) = self.assignee {
+                todo.assignee = Some(assignee);
+            }
+        }
+    }
+
+    // cuerpo de un PATCH segun su Content-Type
+    #[derive(Debug)]
+    pub enum PatchBody {
+        Fields(TodoPatch),
+        Merge(serde_json::Value),
+        Json(json_patch::Patch),
+    }
+
+    impl PatchBody {
+        // la version que mando el cliente, si vino
+        pub fn version(&self) -> Option<u64> {
+            match self {
+                PatchBody::Fields(patch) => patch.version,
+                PatchBody::Merge(document) => document.get("version").and_then(|v| v.as_u64()),
+                // en JSON Patch la version se manda como un test sobre /version
+                PatchBody::Json(ops) => ops.iter().find_map(|op| match op {
+                    json_patch::PatchOperation::Test(test) if test.path.as_str() == "/version" => test.value.as_u64(),
+                    _ => None,
+                }),
+            }
+        }
+    }
+
+    /*
+    Aplica un documento de JSON Merge Patch (RFC 7386) sobre target: cada clave del patch reemplaza
+    a la del target, un null la borra y los objetos se combinan recursivamente.
+     */
+    pub fn json_merge(target: &mut serde_json::Value, patch: &serde_json::Value) {
+        let serde_json::Value::Object(patch) = patch else {
+            *target = patch.clone();
+            return;
+        };
+        if !target.is_object() {
+            *target = serde_json::Value::Object(Default::default());
+        }
+        let target = target.as_object_mut().unwrap();
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                json_merge(target.entry(key.clone()).or_insert(serde_json::Value::Null), value);
+            }
+        }
+    }
+
+    // cuerpo de PATCH /todos/batch: marca todos los ids como completed (o pendientes)
+    #[derive(Deserialize, ToSchema, Debug)]
+    pub struct BatchComplete {
+        pub ids: Vec<i64>,
+        pub completed: bool,
+    }
+
+    // respuesta de PATCH /todos/batch
+    #[derive(Serialize, ToSchema, Debug, Default)]
+    pub struct BatchUpdateResult {
+        pub updated: Vec<i64>,
+        // los que ya tenian ese completed
+        pub unchanged: Vec<i64>,
+        pub missing: Vec<i64>,
+        // ids de las ocurrencias nuevas de los todos recurrentes que se completaron
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub created: Vec<i64>,
+    }
+
+    // cuerpo de PATCH /todos/tags: agrega los tags de add y saca los de remove en cada uno de los ids
+    #[derive(Deserialize, ToSchema, Debug)]
+    pub struct BatchTags {
+        pub ids: Vec<i64>,
+        #[serde(default)]
+        pub add: Vec<String>,
+        #[serde(default)]
+        pub remove: Vec<String>,
+    }
+
+    // respuesta de PATCH /todos/tags; unchanged son los que ya tenian los tags pedidos
+    #[derive(Serialize, ToSchema, Debug, Default)]
+    pub struct BatchTagsResult {
+        pub updated: Vec<i64>,
+        pub unchanged: Vec<i64>,
+        pub missing: Vec<i64>,
+    }
+
+    // version del formato de TodoExport
+    pub const EXPORT_FORMAT: u32 = 1;
+
+    // documento de GET /todos/export, que es el mismo cuerpo que acepta POST /todos/import
+    #[derive(Serialize, Deserialize, ToSchema, Debug)]
+    pub struct TodoExport {
+        pub format: u32,
+        // al importar es solo informativo
+        #[serde(default = "Utc::now")]
+        pub exported_at: DateTime<Utc>,
+        pub todos: Vec<Todo>,
+    }
+
+    // respuesta de POST /todos/import, con los ids de cada grupo
+    #[derive(Serialize, ToSchema, Debug, Default)]
+    pub struct ImportResult {
+        pub imported: Vec<i64>,
+        pub skipped: Vec<i64>,
+        pub conflicted: Vec<i64>,
+    }
+
+    // respuesta de un cambio que completo un todo recurrente: el todo completado y su siguiente ocurrencia
+    #[derive(Serialize, ToSchema, Debug)]
+    pub struct CompletedRecurrence {
+        pub completed: Todo,
+        pub next: Todo,
+    }
+
+    // cuerpo de POST /todos/delete: los ids a borrar
+    #[derive(Deserialize, ToSchema, Debug)]
+    pub struct BatchDelete {
+        pub ids: Vec<i64>,
+    }
+
+    // respuesta de POST /todos/delete
+    #[derive(Serialize, ToSchema, Debug, Default)]
+    pub struct BatchDeleteResult {
+        pub deleted: Vec<i64>,
+        pub missing: Vec<i64>,
+    }
+
+    // cuerpo de POST /todos/<id>/move: el indice (desde 0) donde tiene que quedar el todo
+    #[derive(Deserialize, ToSchema, Debug)]
+    pub struct MoveTodo {
+        pub position: usize,
+    }
+
+    // cuerpo de POST /todos/<id>/reorder: {"before": <id>} o {"after": <id>}, uno solo de los dos
+    #[derive(Deserialize, ToSchema, Debug)]
+    pub struct ReorderTodo {
+        #[serde(default)]
+        pub before: Option<i64>,
+        #[serde(default)]
+        pub after: Option<i64>,
+    }
+
+    #[derive(Deserialize, ToParameters, Debug, Default)]
+    #[salvo(parameters(default_parameter_in = Query))]
+    pub struct ListOptions {
+        pub offset: Option<usize>,
+        pub limit: Option<usize>,
+        pub paged: Option<bool>,
+        // ?completed=true solo terminados, ?completed=false solo pendientes, sin el parametro todos
+        pub completed: Option<bool>,
+        // ?q=milk solo los todos cuyo text contiene "milk", sin importar mayusculas (salvo con CASE_SENSITIVE_SEARCH)
+        pub q: Option<String>,
+        // ?sort_by=id|text|completed|priority|position|updated_at&order=asc|desc, por defecto id asc
+        // (updated_at asc si viene updated_since)
+        pub sort_by: Option<String>,
+        pub order: Option<String>,
+        // ?min_priority=3 solo los todos con priority >= 3 (los que no tienen priority quedan afuera)
+        pub min_priority: Option<u8>,
+        // ?tag=work solo los todos que tienen ese tag (sin importar mayusculas)
+        pub tag: Option<String>,
+        // ?include_deleted=true tambien devuelve los todos borrados (con deleted_at)
+        pub include_deleted: Option<bool>,
+        // ?overdue=true solo los pendientes con due_date vencida, ?overdue=false el resto
+        pub overdue: Option<bool>,
+        // ?min_id=100&max_id=200 solo los ids en ese rango (inclusive), para clientes que sincronizan de a partes
+        pub min_id: Option<i64>,
+        pub max_id: Option<i64>,
+        // ?updated_since=2024-01-01T00:00:00Z solo los todos cambiados despues de ese instante, para sincronizar
+        // desde el ultimo poll. Una fecha que no es RFC 3339 es un bad request
+        pub updated_since: Option<DateTime<Utc>>,
+        // ?format=ndjson devuelve un todo por linea en vez de un arreglo JSON, ?format=csv un archivo CSV
+        pub format: Option<String>,
+        // ?fields=id,text devuelve solo esos campos de cada todo, ver parse_fields (el CSV siempre va completo)
+        pub fields: Option<String>,
+        // ?cursor=<next_cursor de la pagina anterior> sigue despues del ultimo todo de esa pagina, en vez de offset
+        pub cursor: Option<String>,
+        // ?assignee=alice solo los asignados a alice (sin importar mayusculas); ?assignee= o ?unassigned=true
+        // solo los que no tienen assignee
+        pub assignee: Option<String>,
+        pub unassigned: Option<bool>,
+    }
+
+    /*
+    Cursor de la paginacion de GET /todos: el id del ultimo todo de la pagina, en hex de 16 digitos.
+    Para los clientes es opaco, solo lo tienen que devolver tal cual en ?cursor=.
+     */
+    pub fn encode_cursor(id: i64) -> String {
+        format!("{:016x}", id as u64)
+    }
+
+    pub fn decode_cursor(cursor: &str) -> Result<i64, String> {
+        if cursor.len() != 16 {
+            return Err("invalid cursor".to_string());
+        }
+        u64::from_str_radix(cursor, 16)
+            .map(|id| id as i64)
+            .map_err(|_| "invalid cursor".to_string())
+    }
+
+    impl ListOptions {
+        // chequeos entre parametros que parse_queries no puede hacer solo
+        pub fn validate(&self) -> Result<(), String> {
+            if let (Some(min_id), Some(max_id)) = (self.min_id, self.max_id) {
+                if min_id > max_id {
+                    return Err(format!("min_id {min_id} is greater than max_id {max_id}"));
+                }
+            }
+            if self.unassigned == Some(true) && self.assignee.as_deref().and_then(normalize_assignee).is_some() {
+                return Err("assignee and unassigned=true cannot be used together".to_string());
+            }
+            if let Some(cursor) = &self.cursor {
+                decode_cursor(cursor)?;
+                if self.offset.is_some() {
+                    return Err("cursor and offset cannot be used together".to_string());
+                }
+                // el cursor es un id, asi que solo marca un lugar fijo en el orden por id
+                if self.sort_field() != "id" {
+                    return Err("cursor only works with sort_by=id".to_string());
+                }
+            }
+            Ok(())
+        }
+
+        // campo por el que se ordena: sort_by, o el orden por defecto
+        pub fn sort_field(&self) -> &str {
+            // quien sincroniza con updated_since recibe los cambios en el orden en que pasaron
+            let default_sort = if self.updated_since.is_some() { "updated_at" } else { "id" };
+            self.sort_by.as_deref().unwrap_or(default_sort)
+        }
+
+        /*
+        Indica si un todo pasa los filtros de la query. Los filtros que no vienen
+        en la query no restringen nada.
+         */
+        pub fn matches(&self, todo: &Todo, case_sensitive: bool) -> bool {
+            if todo.is_deleted() && !self.include_deleted.unwrap_or(false) {
+                return false;
+            }
+            if todo.archived {
+                return false;
+            }
+            if let Some(completed) = self.completed {
+                if todo.completed != completed {
+                    return false;
+                }
+            }
+            // un q vacio o solo con espacios se trata como si no se hubiera mandado
+            if let Some(q) = self.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+                if !fold_case(&todo.text, case_sensitive).contains(&fold_case(q, case_sensitive)) {
+                    return false;
+                }
+            }
+            if let Some(tag) = self.tag.as_deref().map(str::trim).filter(|tag| !tag.is_empty()) {
+                let tag = tag.to_lowercase();
+                if !todo.tags.contains(&tag) {
+                    return false;
+                }
+            }
+            if let Some(min_priority) = self.min_priority {
+                if todo.priority.is_none_or(|priority| priority < min_priority) {
+                    return false;
+                }
+            }
+            // ?assignee= vacio pide los sin asignar, igual que ?unassigned=true; unassigned=false no filtra
+            let assignee = self.assignee.as_deref().map(normalize_assignee);
+            if self.unassigned == Some(true) || assignee == Some(None) {
+                if todo.assignee.is_some() {
+                    return false;
+                }
+            } else if let Some(Some(assignee)) = assignee {
+                if todo.assignee.as_ref() != Some(&assignee) {
+                    return false;
+                }
+            }
+            if self.min_id.is_some_and(|min_id| todo.id < min_id)
+                || self.max_id.is_some_and(|max_id| todo.id > max_id)
+            {
+                return false;
+            }
+            if let Some(overdue) = self.overdue {
+                if todo.is_overdue(Utc::now()) != overdue {
+                    return false;
+                }
+            }
+            if self.updated_since.is_some_and(|since| todo.updated_at <= since) {
+                return false;
+            }
+            true
+        }
+
+        /*
+        Ordena los todos segun sort_by y order. Devuelve un mensaje de error
+        si alguno de los dos trae un valor que no conocemos.
+         */
+        pub fn sort(&self, todos: &mut [Todo]) -> Result<(), String> {
+            let descending = match self.order.as_deref().unwrap_or("asc") {
+                "asc" => false,
+                "desc" => true,
+                other => return Err(format!("invalid order {other:?}, expected asc or desc")),
+            };
+            // desempatamos por id para que el orden sea siempre el mismo
+            match self.sort_field() {
+                "id" => todos.sort_by_key(|todo| todo.id),
+                "text" => todos.sort_by(|a, b| a.text.cmp(&b.text).then(a.id.cmp(&b.id))),
+                "completed" => todos.sort_by_key(|todo| (todo.completed, todo.id)),
+                // None es menor que cualquier Some, asi que en desc los que no tienen priority quedan al final
+                "priority" => todos.sort_by_key(|todo| (todo.priority, todo.id)),
+                "position" => todos.sort_by_key(|todo| (todo.position, todo.id)),
+                "updated_at" => todos.sort_by_key(|todo| (todo.updated_at, todo.id)),
+                other => {
+                    return Err(format!(
+                        "invalid sort_by {other:?}, expected id, text, completed, priority, position or updated_at"
+                    ))
+                }
+            }
+            if descending {
+                todos.reverse();
+            }
+            Ok(())
+        }
+    }
+
+    // respuesta de GET /todos/next-id
+    #[derive(Serialize, ToSchema, Debug)]
+    pub struct NextId {
+        pub next_id: i64,
+    }
+
+    // respuesta de GET /todos/board: los todos filtrados separados en las dos columnas
+    #[derive(Serialize, ToSchema, Debug, Default)]
+    pub struct TodoBoard {
+        pub pending: Vec<Todo>,
+        pub completed: Vec<Todo>,
+    }
+
+    // query de GET /todos/search?q=...&limit=...
+    #[derive(Deserialize, ToParameters, Debug, Default)]
+    #[salvo(parameters(default_parameter_in = Query))]
+    pub struct SearchOptions {
+        // palabras a buscar en el text y los tags de los todos
+        pub q: Option<String>,
+        // cuantos resultados devolver, como mucho SEARCH_MAX_RESULTS
+        pub limit: Option<usize>,
+    }
+
+    // un resultado de GET /todos/search: el todo y que tan bien coincide, ver Todo::search_score
+    #[derive(Serialize, ToSchema, Debug)]
+    pub struct SearchResult {
+        pub score: u32,
+        pub todo: Todo,
+    }
+
+    // totales que devuelve GET /todos/count
+    #[derive(Serialize, ToSchema, Debug, Default)]
+    pub struct TodoCounts {
+        pub total: usize,
+        pub completed: usize,
+        pub pending: usize,
+    }
+
+    impl TodoCounts {
+        pub fn add(&mut self, todo: &Todo) {
+            self.total += 1;
+            if todo.completed {
+                self.completed += 1;
+            } else {
+                self.pending += 1;
+            }
+        }
+    }
+
+    /*
+    Resumen para dashboards que devuelve GET /todos/stats: los mismos totales que /count
+    mas cuantos todos hay de cada priority (solo aparece si algun todo tiene priority).
+     */
+    #[derive(Serialize, ToSchema, Debug, Default)]
+    pub struct TodoStats {
+        #[serde(flatten)]
+        pub counts: TodoCounts,
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub by_priority: BTreeMap<u8, usize>,
+    }
+
+    /*
+    PagedResponse envuelve una pagina de resultados junto con el total de elementos
+    (antes de paginar) y los parametros de paginacion que se usaron.
+     */
+    #[derive(Serialize, Debug)]
+    pub struct PagedResponse<T> {
+        pub items: Vec<T>,
+        pub total: usize,
+        pub offset: usize,
+        pub limit: Option<usize>,
+        // cursor para pedir la pagina siguiente con ?cursor=, si la hay y se ordena por id
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub next_cursor: Option<String>,
+    }
+}
\ No newline at end of file
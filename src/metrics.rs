@@ -0,0 +1,75 @@
+/*
+Metricas en formato de texto de Prometheus para GET /metrics. Los nombres son estables, si se cambian
+hay que avisar porque rompen dashboards y alertas:
+
+todo_api_requests_total                  requests recibidas
+todo_api_requests_by_method_total{method} requests por metodo HTTP (los metodos raros van como "OTHER")
+todo_api_error_responses_total           respuestas con status 4xx o 5xx
+todo_api_todos                           todos guardados ahora (sin contar los borrados)
+*/
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use salvo::http::Method;
+use salvo::prelude::*;
+
+// metodos que se cuentan por separado, el resto suma en "OTHER"
+const METHODS: [&str; 7] = ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS", "HEAD"];
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static REQUESTS_BY_METHOD: [AtomicU64; METHODS.len() + 1] = [const { AtomicU64::new(0) }; METHODS.len() + 1];
+static ERROR_RESPONSES: AtomicU64 = AtomicU64::new(0);
+
+fn method_index(method: &Method) -> usize {
+    METHODS
+        .iter()
+        .position(|name| *name == method.as_str())
+        .unwrap_or(METHODS.len())
+}
+
+/*
+Hoop que cuenta cada request y, al volver, si la respuesta fue un error. Igual que en request_id,
+el 404 de una ruta inexistente lo pone salvo despues de los hoops, asi que esos no se cuentan como error.
+*/
+#[handler]
+pub async fn track(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+    REQUESTS_BY_METHOD[method_index(req.method())].fetch_add(1, Ordering::Relaxed);
+
+    ctrl.call_next(req, depot, res).await;
+
+    let status = res.status_code.unwrap_or(StatusCode::OK);
+    if status.is_client_error() || status.is_server_error() {
+        ERROR_RESPONSES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// arma el texto de /metrics; la cantidad de todos la pasa el handler porque vive en el STORE
+pub fn render(todos: usize) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "# HELP todo_api_requests_total Total number of requests received.").ok();
+    writeln!(out, "# TYPE todo_api_requests_total counter").ok();
+    writeln!(out, "todo_api_requests_total {}", REQUESTS.load(Ordering::Relaxed)).ok();
+
+    writeln!(out, "# HELP todo_api_requests_by_method_total Requests received by HTTP method.").ok();
+    writeln!(out, "# TYPE todo_api_requests_by_method_total counter").ok();
+    for (name, count) in METHODS.iter().chain(["OTHER"].iter()).zip(&REQUESTS_BY_METHOD) {
+        writeln!(
+            out,
+            "todo_api_requests_by_method_total{{method=\"{name}\"}} {}",
+            count.load(Ordering::Relaxed)
+        )
+        .ok();
+    }
+
+    writeln!(out, "# HELP todo_api_error_responses_total Responses with a 4xx or 5xx status.").ok();
+    writeln!(out, "# TYPE todo_api_error_responses_total counter").ok();
+    writeln!(out, "todo_api_error_responses_total {}", ERROR_RESPONSES.load(Ordering::Relaxed)).ok();
+
+    writeln!(out, "# HELP todo_api_todos Number of todos currently stored.").ok();
+    writeln!(out, "# TYPE todo_api_todos gauge").ok();
+    writeln!(out, "todo_api_todos {todos}").ok();
+
+    out
+}
@@ -0,0 +1,629 @@
+/*
+Middlewares (hoops) que se aplican a todas las rutas desde service() o route().
+*/
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use salvo::compression::{Compression, CompressionLevel};
+use salvo::http::header::{self, HeaderValue};
+use salvo::http::{mime, Body, Method, ResBody};
+use salvo::prelude::*;
+use salvo::routing::PathState;
+use uuid::Uuid;
+
+use crate::errors::ApiError;
+use crate::tenant;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// clave con la que se guarda el id de la request en el depot
+pub const REQUEST_ID_KEY: &str = "request_id";
+
+/*
+Asigna un id a cada request (o reutiliza el X-Request-Id que manda el cliente), lo guarda en el depot
+para que los handlers lo puedan usar, lo devuelve en el header X-Request-Id de la respuesta y
+al terminar loguea metodo, path, status y cuanto tardo.
+*/
+#[handler]
+pub async fn request_id(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let id = req
+        .header::<String>(REQUEST_ID_HEADER)
+        .filter(|id| !id.is_empty() && HeaderValue::from_str(id).is_ok())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    depot.insert(REQUEST_ID_KEY, id.clone());
+
+    let start = Instant::now();
+    ctrl.call_next(req, depot, res).await;
+    let elapsed = start.elapsed();
+
+    // si ningun handler puso status la respuesta sale con 200; para rutas inexistentes salvo
+    // pone el 404 recien despues de los hoops, asi que aca todavia no lo vemos
+    let status = res.status_code.unwrap_or(StatusCode::OK);
+    tracing::info!(
+        request_id = %id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        status = status.as_u16(),
+        elapsed_ms = elapsed.as_secs_f64() * 1000.0,
+        "request completed"
+    );
+    res.add_header(REQUEST_ID_HEADER, id, true).ok();
+}
+
+// valores por defecto del rate limiter si no se definen RATE_LIMIT_RPS y RATE_LIMIT_BURST; sin RATE_LIMIT_RPS
+// queda apagado, detras de un proxy todas las requests llegan con la IP del proxy y se cortarian entre si
+pub const DEFAULT_RATE_LIMIT_RPS: f64 = 0.0;
+pub const DEFAULT_RATE_LIMIT_BURST: f64 = 20.0;
+
+// cada cuanto, como minimo, se recorre el mapa para sacar los buckets que ya no hacen falta
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/*
+Token bucket de un cliente: tokens disponibles y cuando se recargo por ultima vez.
+*/
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+struct Buckets {
+    by_ip: HashMap<IpAddr, Bucket>,
+    last_prune: Instant,
+}
+
+/*
+Rate limiter por IP del cliente con token bucket: cada IP arranca con `burst` tokens,
+cada request consume uno y se recargan a razon de `rate` por segundo.
+Sin tokens la request se corta con 429 y un Retry-After en segundos.
+*/
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: f64) -> Self {
+        RateLimiter {
+            rate,
+            burst,
+            buckets: Mutex::new(Buckets {
+                by_ip: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+        }
+    }
+
+    /*
+    Lee RATE_LIMIT_RPS y RATE_LIMIT_BURST. Hay que pedirlo: sin RATE_LIMIT_RPS, o con 0, queda desactivado.
+    */
+    pub fn from_env() -> Option<Self> {
+        let rate = env_f64("RATE_LIMIT_RPS", DEFAULT_RATE_LIMIT_RPS);
+        let burst = env_f64("RATE_LIMIT_BURST", DEFAULT_RATE_LIMIT_BURST);
+        if rate <= 0.0 {
+            tracing::info!("rate limiting disabled");
+            return None;
+        }
+        tracing::info!(rate, burst, "rate limiting enabled");
+        Some(Self::new(rate, burst.max(1.0)))
+    }
+
+    /*
+    Intenta consumir un token de la IP. Si no hay, devuelve cuanto falta para el proximo.
+    */
+    pub fn try_acquire(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if now.duration_since(buckets.last_prune) >= PRUNE_INTERVAL {
+            // un bucket que ya se habria recargado entero es igual a uno nuevo, asi que lo podemos sacar
+            let full_after = Duration::from_secs_f64(self.burst / self.rate);
+            buckets
+                .by_ip
+                .retain(|_, bucket| now.duration_since(bucket.last_refill) < full_after);
+            buckets.last_prune = now;
+        }
+
+        let bucket = buckets.by_ip.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            last_refill: now,
+        });
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+}
+
+#[handler]
+impl RateLimiter {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        // sin direccion remota (por ejemplo en tests) no hay con que agrupar, la dejamos pasar
+        let Some(ip) = req.remote_addr().clone().into_std().map(|addr| addr.ip()) else {
+            ctrl.call_next(req, depot, res).await;
+            return;
+        };
+
+        if let Err(wait) = self.try_acquire(ip, Instant::now()) {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            tracing::debug!(%ip, retry_after, "rate limit exceeded");
+            res.status_code(StatusCode::TOO_MANY_REQUESTS);
+            res.add_header(header::RETRY_AFTER, retry_after, true).ok();
+            res.render(Json(ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too many requests",
+            )));
+            ctrl.skip_rest();
+            return;
+        }
+        ctrl.call_next(req, depot, res).await;
+    }
+}
+
+// tamaño maximo del cuerpo de una request, en bytes, si no se define MAX_BODY_SIZE (16 KiB)
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 16 * 1024;
+
+// lo mismo para los endpoints que reciben muchos todos (batch, delete, import) si no se define MAX_BULK_BODY_SIZE (1 MiB)
+pub const DEFAULT_MAX_BULK_BODY_SIZE: u64 = 1024 * 1024;
+
+/*
+Limite de tamaño del cuerpo. Hace lo mismo que salvo::size_limiter pero responde con el error JSON
+de la API: 413 si el cuerpo supera `max` bytes y 400 si no se puede saber su tamaño de antemano.
+*/
+pub struct BodySizeLimit {
+    max: u64,
+}
+
+impl BodySizeLimit {
+    pub fn new(max: u64) -> Self {
+        BodySizeLimit { max }
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    // lee el limite en bytes de MAX_BODY_SIZE, por defecto DEFAULT_MAX_BODY_SIZE
+    pub fn from_env() -> Self {
+        Self::new(env_u64("MAX_BODY_SIZE", DEFAULT_MAX_BODY_SIZE))
+    }
+
+    /*
+    Limite de los endpoints que reciben muchos todos de una vez, de MAX_BULK_BODY_SIZE. Por defecto es
+    DEFAULT_MAX_BULK_BODY_SIZE, o MAX_BODY_SIZE si es mas grande, asi subir MAX_BODY_SIZE sigue alcanzando para todo.
+    */
+    pub fn bulk_from_env() -> Self {
+        let default = DEFAULT_MAX_BULK_BODY_SIZE.max(Self::from_env().max());
+        Self::new(env_u64("MAX_BULK_BODY_SIZE", default))
+    }
+}
+
+#[handler]
+impl BodySizeLimit {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        match req.body().size_hint().upper() {
+            Some(size) if size <= self.max => {
+                ctrl.call_next(req, depot, res).await;
+            }
+            Some(size) => {
+                tracing::debug!(size, max = self.max, "request body too large");
+                res.status_code(StatusCode::PAYLOAD_TOO_LARGE);
+                res.render(Json(ApiError::payload_too_large(self.max)));
+                ctrl.skip_rest();
+            }
+            None => {
+                res.status_code(StatusCode::BAD_REQUEST);
+                res.render(Json(ApiError::bad_request("request body size is unknown")));
+                ctrl.skip_rest();
+            }
+        }
+    }
+}
+
+/*
+Rechaza con 413 las requests que declaran un Content-Length mas grande que `max` antes de que nadie toque
+el cuerpo. BodySizeLimit mira el tamaño del cuerpo en cada ruta; este va en service(), asi un cliente que
+anuncia un cuerpo enorme ni siquiera llega a los hoops que lo leen (como DebugBodies).
+*/
+pub struct ContentLengthLimit {
+    max: u64,
+}
+
+impl ContentLengthLimit {
+    pub fn new(max: u64) -> Self {
+        ContentLengthLimit { max }
+    }
+
+    // el mayor de los limites de BodySizeLimit: cada router despues aplica el suyo
+    pub fn from_env() -> Self {
+        Self::new(BodySizeLimit::from_env().max().max(BodySizeLimit::bulk_from_env().max()))
+    }
+}
+
+#[handler]
+impl ContentLengthLimit {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        // un Content-Length que no es un numero lo rechaza hyper antes de llegar aca
+        match req.header::<u64>(header::CONTENT_LENGTH) {
+            Some(length) if length > self.max => {
+                tracing::debug!(length, max = self.max, "declared content length too large");
+                res.status_code(StatusCode::PAYLOAD_TOO_LARGE);
+                res.render(Json(ApiError::payload_too_large(self.max)));
+                ctrl.skip_rest();
+            }
+            _ => {
+                ctrl.call_next(req, depot, res).await;
+            }
+        }
+    }
+}
+
+/*
+Headers de seguridad para lo que se abre en un navegador (Swagger UI, GraphiQL): X-Content-Type-Options: nosniff
+y X-Frame-Options: DENY en todas las respuestas, y Content-Security-Policy si se define CONTENT_SECURITY_POLICY.
+No hay una politica por defecto porque Swagger UI y GraphiQL usan scripts inline y de CDN que cualquier politica
+estricta rompe; quien los publique elige cual le sirve. SECURITY_HEADERS=false los apaga.
+*/
+pub struct SecurityHeaders {
+    csp: Option<HeaderValue>,
+}
+
+impl SecurityHeaders {
+    pub fn new(csp: Option<HeaderValue>) -> Self {
+        SecurityHeaders { csp }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        if std::env::var("SECURITY_HEADERS").is_ok_and(|value| value == "false") {
+            tracing::info!("security headers disabled");
+            return None;
+        }
+        let csp = std::env::var("CONTENT_SECURITY_POLICY").ok().map(|policy| {
+            HeaderValue::from_str(&policy)
+                .unwrap_or_else(|e| panic!("invalid CONTENT_SECURITY_POLICY {policy:?}: {e}"))
+        });
+        Some(Self::new(csp))
+    }
+}
+
+#[handler]
+impl SecurityHeaders {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        ctrl.call_next(req, depot, res).await;
+        // despues del handler, asi tambien quedan en los errores y en las respuestas del catcher
+        let headers = res.headers_mut();
+        headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        if let Some(csp) = &self.csp {
+            headers.insert(header::CONTENT_SECURITY_POLICY, csp.clone());
+        }
+    }
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid {name} {value:?}: {e}")),
+        Err(_) => default,
+    }
+}
+
+fn env_f64(name: &str, default: f64) -> f64 {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid {name} {value:?}: {e}")),
+        Err(_) => default,
+    }
+}
+
+/*
+Autenticacion por API key: exige `Authorization: Bearer <key>` en las requests que modifican datos
+(POST, PUT, PATCH, DELETE) y, si se pide con protect_reads, tambien en las de lectura.
+Es un hoop comun, asi que se puede agregar solo a los routers que lo necesiten.
+*/
+pub struct ApiKeyAuth {
+    key: String,
+    protect_reads: bool,
+}
+
+impl ApiKeyAuth {
+    pub fn new(key: impl Into<String>) -> Self {
+        ApiKeyAuth {
+            key: key.into(),
+            protect_reads: false,
+        }
+    }
+
+    pub fn protect_reads(mut self, protect_reads: bool) -> Self {
+        self.protect_reads = protect_reads;
+        self
+    }
+
+    /*
+    Lee la key de API_KEY. Si no esta definida la API queda abierta, como antes.
+    Con API_KEY_PROTECT_READS=true tambien se exige la key para GET.
+    */
+    pub fn from_env() -> Option<Self> {
+        let key = std::env::var("API_KEY").ok().filter(|key| !key.is_empty())?;
+        let protect_reads = std::env::var("API_KEY_PROTECT_READS").is_ok_and(|value| value == "true");
+        Some(Self::new(key).protect_reads(protect_reads))
+    }
+
+    fn requires_key(&self, method: &Method) -> bool {
+        self.protect_reads
+            || matches!(
+                *method,
+                Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+            )
+    }
+}
+
+#[handler]
+impl ApiKeyAuth {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        if !self.requires_key(req.method()) {
+            ctrl.call_next(req, depot, res).await;
+            return;
+        }
+
+        let token = req
+            .header::<String>(header::AUTHORIZATION)
+            .and_then(|value| value.strip_prefix("Bearer ").map(str::to_string));
+        let authorized = token.is_some_and(|token| constant_time_eq(token.as_bytes(), self.key.as_bytes()));
+        if !authorized {
+            tracing::debug!(method = %req.method(), path = %req.uri().path(), "missing or invalid api key");
+            res.status_code(StatusCode::UNAUTHORIZED);
+            res.add_header(header::WWW_AUTHENTICATE, "Bearer", true).ok();
+            res.render(Json(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "missing or invalid api key",
+            )));
+            ctrl.skip_rest();
+            return;
+        }
+        ctrl.call_next(req, depot, res).await;
+    }
+}
+
+// compara sin cortar en el primer byte distinto, para no filtrar la key por tiempos de respuesta
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/*
+En modo multi-tenant (ver el modulo tenant) corre el resto de la request como el usuario de X-User-Id.
+Sin el header, o con uno invalido, responde 400. Fuera de ese modo no hace nada.
+*/
+#[handler]
+pub async fn tenant_scope(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    if !tenant::enabled() {
+        ctrl.call_next(req, depot, res).await;
+        return;
+    }
+    let user = req.header::<String>(tenant::USER_ID_HEADER).unwrap_or_default();
+    if let Err(message) = tenant::validate_user_id(&user) {
+        tracing::debug!(%message, "invalid user id");
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(Json(ApiError::bad_request(message)));
+        ctrl.skip_rest();
+        return;
+    }
+    // GET, HEAD y OPTIONS no crean el store de un usuario que todavia no tiene todos
+    let read_only = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let store = match crate::tenant_store(&user, read_only) {
+        Ok(store) => store,
+        Err(error) => {
+            res.status_code(error.status());
+            res.render(Json(error));
+            ctrl.skip_rest();
+            return;
+        }
+    };
+    tenant::scope(user, store, ctrl.call_next(req, depot, res)).await;
+}
+
+// metodos que se prueban para armar el header Allow de un 405
+const ROUTED_METHODS: [Method; 5] = [Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE];
+
+/*
+Hoop para el Catcher del Service. Cuando salvo no encuentra ruta responde 404, aunque el path
+exista con otros metodos (por ejemplo PATCH /todos). Aca probamos el mismo path con cada metodo
+contra el router: si alguno coincide respondemos 405 con el header Allow.
+*/
+pub struct MethodNotAllowed {
+    router: Arc<Router>,
+}
+
+impl MethodNotAllowed {
+    pub fn new(router: Arc<Router>) -> Self {
+        MethodNotAllowed { router }
+    }
+
+    fn allowed_methods(&self, req: &Request) -> Vec<Method> {
+        ROUTED_METHODS
+            .iter()
+            .filter(|method| {
+                let mut probe = Request::new();
+                *probe.uri_mut() = req.uri().clone();
+                *probe.method_mut() = (*method).clone();
+                let mut path_state = PathState::new(req.uri().path());
+                self.router.detect(&mut probe, &mut path_state).is_some()
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[handler]
+impl MethodNotAllowed {
+    async fn handle(&self, req: &mut Request, res: &mut Response, ctrl: &mut FlowCtrl) {
+        if res.status_code != Some(StatusCode::NOT_FOUND) {
+            return;
+        }
+        let allowed = self.allowed_methods(req);
+        if allowed.is_empty() {
+            return;
+        }
+        let allow = allowed.iter().map(Method::as_str).collect::<Vec<_>>().join(", ");
+        tracing::debug!(method = %req.method(), path = %req.uri().path(), %allow, "method not allowed");
+        res.status_code(StatusCode::METHOD_NOT_ALLOWED);
+        res.add_header(header::ALLOW, allow, true).ok();
+        res.render(Json(ApiError::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            format!("method {} not allowed", req.method()),
+        )));
+        ctrl.skip_rest();
+    }
+}
+
+/*
+Ultimo hoop del Catcher: un 404 que llega hasta aca (sin cuerpo y sin que MethodNotAllowed lo tome) es una ruta
+que no existe, por ejemplo un path mal escrito. Lo respondemos con el mismo JSON que el resto de los errores.
+*/
+#[handler]
+pub async fn route_not_found(req: &mut Request, res: &mut Response, ctrl: &mut FlowCtrl) {
+    if res.status_code != Some(StatusCode::NOT_FOUND) {
+        return;
+    }
+    tracing::debug!(method = %req.method(), path = %req.uri().path(), "no route");
+    res.render(Json(ApiError::route_not_found(req.uri().path())));
+    ctrl.skip_rest();
+}
+
+// respuestas mas chicas que esto (en bytes) no se comprimen, si no se define COMPRESSION_MIN_SIZE
+pub const DEFAULT_COMPRESSION_MIN_SIZE: usize = 1024;
+
+/*
+Compresion gzip o brotli de las respuestas segun el Accept-Encoding del cliente. Esta activa salvo que
+se defina COMPRESSION=false; COMPRESSION_MIN_SIZE cambia el tamaño minimo a partir del cual se comprime.
+Solo se comprime JSON, CSV y texto plano: el SSE de /todos/stream y el NDJSON de list_todos quedan afuera
+porque el compresor junta datos antes de escribir y los eventos o lineas llegarian tarde al cliente.
+*/
+pub fn compression_from_env() -> Option<Compression> {
+    if std::env::var("COMPRESSION").is_ok_and(|value| value == "false") {
+        return None;
+    }
+    let min_size = match std::env::var("COMPRESSION_MIN_SIZE") {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid COMPRESSION_MIN_SIZE {value:?}: {e}")),
+        Err(_) => DEFAULT_COMPRESSION_MIN_SIZE,
+    };
+    let compression = Compression::new()
+        .disable_all()
+        .enable_gzip(CompressionLevel::Default)
+        .enable_brotli(CompressionLevel::Default)
+        .min_length(min_size)
+        .content_types(&[mime::APPLICATION_JSON, mime::TEXT_CSV, mime::TEXT_PLAIN]);
+    Some(compression)
+}
+
+/*
+Con ?pretty=true las respuestas JSON salen indentadas, para leerlas con curl; por defecto salen compactas.
+Se vuelve a formatear el cuerpo despues del handler, asi vale para cualquier ruta y tambien para los errores;
+los 404 y 405 del catcher pasan por su propia copia de este hoop, ver service().
+serde_json tiene preserve_order, asi los campos quedan en el mismo orden que en la respuesta compacta.
+*/
+#[handler]
+pub async fn pretty_json(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    ctrl.call_next(req, depot, res).await;
+    if !req.query::<bool>("pretty").unwrap_or(false) {
+        return;
+    }
+    let is_json = res
+        .content_type()
+        .is_some_and(|mime| mime.essence_str() == mime::APPLICATION_JSON.essence_str());
+    let ResBody::Once(bytes) = &res.body else {
+        return;
+    };
+    if !is_json {
+        return;
+    }
+    let pretty = serde_json::from_slice::<serde_json::Value>(bytes)
+        .and_then(|value| serde_json::to_vec_pretty(&value));
+    if let Ok(pretty) = pretty {
+        res.body = ResBody::Once(pretty.into());
+    }
+}
+
+// cuantos bytes de cada cuerpo se loguean con DEBUG_BODIES, el resto se corta
+pub const MAX_LOGGED_BODY: usize = 1024;
+
+/*
+DEBUG_BODIES=true prende DebugBodies. Esta apagado por defecto porque los cuerpos pueden traer datos
+de los usuarios y no tienen que terminar en los logs de produccion.
+*/
+pub fn debug_bodies_enabled() -> bool {
+    std::env::var("DEBUG_BODIES").is_ok_and(|value| value == "true")
+}
+
+/*
+Loguea con tracing::debug! el cuerpo de la request y el de la respuesta, cortados a MAX_LOGGED_BODY bytes,
+para ver por que se rechaza lo que manda un cliente. El cuerpo de la request se lee con payload(), que lo
+deja guardado en la request y los handlers lo siguen pudiendo parsear. Como va antes que los BodySizeLimit de
+cada ruta y un cuerpo ya leido no tiene tamaño para ellos, solo se lee hasta `max`, el menor de esos limites:
+lo que pasa de ahi, o no tiene largo conocido, no se lee y cada ruta lo sigue rechazando como siempre.
+Las respuestas en stream (SSE, NDJSON) tampoco se loguean.
+*/
+pub struct DebugBodies {
+    max: u64,
+}
+
+impl DebugBodies {
+    pub fn new(max: u64) -> Self {
+        DebugBodies { max }
+    }
+
+    // el menor entre MAX_BODY_SIZE y MAX_BULK_BODY_SIZE, lo que cualquier ruta aceptaria
+    pub fn from_env() -> Self {
+        Self::new(BodySizeLimit::from_env().max().min(BodySizeLimit::bulk_from_env().max()))
+    }
+}
+
+#[handler]
+impl DebugBodies {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        let size = req.body().size_hint().upper();
+        match size {
+            Some(0) => {}
+            Some(size) if size <= self.max => {
+                if let Ok(payload) = req.payload().await {
+                    tracing::debug!(%method, %path, body = %truncated(payload), "request body");
+                }
+            }
+            _ => tracing::debug!(%method, %path, ?size, "request body not logged"),
+        }
+
+        ctrl.call_next(req, depot, res).await;
+
+        let body = match &res.body {
+            ResBody::Once(bytes) => Some(truncated(bytes)),
+            ResBody::Chunks(chunks) => Some(truncated(&chunks.iter().flatten().copied().collect::<Vec<u8>>())),
+            _ => None,
+        };
+        if let Some(body) = body {
+            let status = res.status_code.unwrap_or(StatusCode::OK).as_u16();
+            tracing::debug!(%method, %path, status, %body, "response body");
+        }
+    }
+}
+
+fn truncated(body: &[u8]) -> String {
+    let text = String::from_utf8_lossy(&body[..body.len().min(MAX_LOGGED_BODY)]);
+    if body.len() > MAX_LOGGED_BODY {
+        format!("{text}... ({} bytes)", body.len())
+    } else {
+        text.into_owned()
+    }
+}
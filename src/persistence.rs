@@ -0,0 +1,95 @@
+/*
+Modulo de persistencia: guarda el contenido del STORE en un archivo JSON
+para que los todos sobrevivan a un reinicio del servidor.
+En modo multi-tenant cada usuario tiene su archivo, ver todos_file.
+*/
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::models::{Todo, FIRST_VERSION};
+use crate::tenant;
+
+// archivo donde se guardan los todos si no se define TODOS_FILE, relativo al directorio de trabajo
+pub const DEFAULT_TODOS_FILE: &str = "todos.json";
+
+/*
+Ruta del archivo de todos, se puede cambiar con la variable de entorno TODOS_FILE.
+Durante una request de un usuario (modo multi-tenant) es el archivo de ese usuario, al lado:
+con TODOS_FILE=data/todos.json los de "ana" van en data/todos.ana.json.
+*/
+pub fn todos_file() -> PathBuf {
+    let file = std::env::var_os("TODOS_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_TODOS_FILE));
+    match tenant::current() {
+        Some(user) => tenant_file_in(&file, &user),
+        None => file,
+    }
+}
+
+// el archivo del usuario user, aunque no estemos en una request suya
+pub fn tenant_file(user: &str) -> PathBuf {
+    let file = std::env::var_os("TODOS_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_TODOS_FILE));
+    tenant_file_in(&file, user)
+}
+
+fn tenant_file_in(file: &Path, user: &str) -> PathBuf {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    file.with_file_name(format!("{stem}.{user}.json"))
+}
+
+/*
+Lee los todos desde todos_file() al arrancar. En disco se guardan como un arreglo JSON y en memoria
+se indexan por id. Si el archivo no existe arrancamos con un store vacio.
+Si existe pero no se puede leer o parsear preferimos abortar antes que pisarlo
+con un store vacio y perder los datos.
+*/
+pub fn load() -> HashMap<i64, Todo> {
+    load_from(&todos_file()).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/*
+Como load, pero devuelve el error en vez de abortar: los archivos de cada usuario se leen durante una request
+y uno roto no tiene que tirar el servidor. Un archivo que no existe es un store vacio, no un error.
+*/
+pub fn load_from(path: &Path) -> Result<HashMap<i64, Todo>, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+    };
+    let todos = serde_json::from_slice::<Vec<Todo>>(&bytes)
+        .map_err(|e| format!("failed to parse {}: {e}", path.display()))?;
+    Ok(todos
+        .into_iter()
+        // los archivos de antes de que existiera version no la traen
+        .map(|mut todo| {
+            todo.version = todo.version.max(FIRST_VERSION);
+            (todo.id, todo)
+        })
+        .collect())
+}
+
+/*
+Escribe los todos en todos_file(), ordenados por id para que el archivo sea estable. Primero escribimos a un archivo temporal y despues
+lo renombramos, asi nunca queda un archivo a medio escribir si el proceso muere.
+Los errores solo se loguean: la request ya se aplico en memoria.
+*/
+pub fn save(todos: &HashMap<i64, Todo>) {
+    if let Err(e) = save_to(&todos_file(), todos) {
+        tracing::error!(error = ?e, "failed to persist todos");
+    }
+}
+
+pub fn save_to(path: &Path, todos: &HashMap<i64, Todo>) -> std::io::Result<()> {
+    let mut todos: Vec<&Todo> = todos.values().collect();
+    todos.sort_by_key(|todo| todo.id);
+    let bytes = serde_json::to_vec_pretty(&todos)?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,488 @@
+/*
+Backend SQLite: con DATABASE_URL=sqlite:<archivo> (por ejemplo sqlite:todos.db) el store es una base SQLite en vez
+de todos.json, asi varias instancias del servidor pueden compartir los mismos todos. Sin la variable se sigue
+usando el archivo JSON de TODOS_FILE. En modo multi-tenant cada usuario tiene su base al lado, todos.<usuario>.db.
+
+Cada fila guarda el todo entero como JSON (el mismo que devuelve la API) junto con su id y su version, que son
+lo que usan las consultas: asi un campo nuevo en Todo no necesita una migracion. Las tablas se crean al abrir la
+base con MIGRATIONS, y PRAGMA user_version guarda cuantas ya corrieron.
+
+Hablamos con la libsqlite3 del sistema directamente, con las pocas funciones de su API en C que hacen falta.
+Las consultas se hacen de a una, detras de un Mutex; cada operacion que lee y escribe va en una transaccion
+BEGIN IMMEDIATE, asi otra instancia no escribe en el medio. Si la base falla en medio de una request (disco lleno,
+archivo roto) no hay nada que la request pueda hacer: se loguea y se corta con panic, despues de un ROLLBACK.
+*/
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_uchar, c_void, CStr, CString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+
+use crate::models::Todo;
+use crate::store::{Change, StoreError, TodoStore};
+
+// el prefijo de DATABASE_URL para una base SQLite
+pub const URL_SCHEME: &str = "sqlite:";
+
+// lo que espera una consulta a que otra instancia suelte la base antes de fallar
+const BUSY_TIMEOUT_MS: c_int = 5_000;
+
+// cada migracion corre una sola vez y en orden; no se cambian las que ya salieron, se agrega una nueva
+const MIGRATIONS: &[&[&str]] = &[&[
+    "CREATE TABLE todos (id INTEGER PRIMARY KEY, version INTEGER NOT NULL, todo TEXT NOT NULL)",
+    // una sola fila: cuando cambio el store por ultima vez, en milisegundos
+    "CREATE TABLE store (last_changed INTEGER NOT NULL)",
+    "INSERT INTO store (last_changed) VALUES (CAST(strftime('%s', 'now') AS INTEGER) * 1000)",
+]];
+
+#[allow(non_camel_case_types)]
+enum sqlite3 {}
+
+#[allow(non_camel_case_types)]
+enum sqlite3_stmt {}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_CONSTRAINT: c_int = 19;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_OPEN_READWRITE: c_int = 0x2;
+const SQLITE_OPEN_CREATE: c_int = 0x4;
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(filename: *const c_char, db: *mut *mut sqlite3, flags: c_int, vfs: *const c_char) -> c_int;
+    fn sqlite3_close_v2(db: *mut sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut sqlite3) -> *const c_char;
+    fn sqlite3_busy_timeout(db: *mut sqlite3, ms: c_int) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut sqlite3,
+        sql: *const c_char,
+        len: c_int,
+        stmt: *mut *mut sqlite3_stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_step(stmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_finalize(stmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_bind_int64(stmt: *mut sqlite3_stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_bind_text(
+        stmt: *mut sqlite3_stmt,
+        index: c_int,
+        text: *const c_char,
+        len: c_int,
+        destructor: *const c_void,
+    ) -> c_int;
+    fn sqlite3_column_int64(stmt: *mut sqlite3_stmt, column: c_int) -> i64;
+    fn sqlite3_column_text(stmt: *mut sqlite3_stmt, column: c_int) -> *const c_uchar;
+    fn sqlite3_column_bytes(stmt: *mut sqlite3_stmt, column: c_int) -> c_int;
+}
+
+// SQLITE_TRANSIENT: sqlite se copia el texto, asi no tiene que vivir mas que la llamada
+const SQLITE_TRANSIENT: *const c_void = -1isize as *const c_void;
+
+#[derive(Debug)]
+struct Error {
+    code: c_int,
+    message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (sqlite error {})", self.message, self.code)
+    }
+}
+
+// un parametro de una consulta, en el orden de sus ?
+enum Param<'a> {
+    Int(i64),
+    Text(&'a str),
+}
+
+struct Connection(*mut sqlite3);
+
+// libsqlite3 viene compilada thread-safe; igual la usamos de a un hilo por vez, detras del Mutex de SqliteStore
+unsafe impl Send for Connection {}
+
+impl Connection {
+    fn open(path: &Path) -> Result<Self, Error> {
+        let filename = CString::new(path.to_string_lossy().as_bytes()).map_err(|_| Error {
+            code: 0,
+            message: format!("invalid database path {}", path.display()),
+        })?;
+        let mut db = std::ptr::null_mut();
+        let code = unsafe {
+            sqlite3_open_v2(filename.as_ptr(), &mut db, SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE, std::ptr::null())
+        };
+        // aunque falle, sqlite devuelve una conexion para poder leer el error; Drop la cierra
+        let connection = Connection(db);
+        if code != SQLITE_OK {
+            return Err(connection.error(code));
+        }
+        unsafe { sqlite3_busy_timeout(db, BUSY_TIMEOUT_MS) };
+        Ok(connection)
+    }
+
+    fn error(&self, code: c_int) -> Error {
+        let message = if self.0.is_null() {
+            "out of memory".to_string()
+        } else {
+            unsafe { CStr::from_ptr(sqlite3_errmsg(self.0)) }.to_string_lossy().into_owned()
+        };
+        Error { code, message }
+    }
+
+    // corre sql con params y llama a row por cada fila que devuelve
+    fn query(&self, sql: &str, params: &[Param], mut row: impl FnMut(&Row)) -> Result<(), Error> {
+        let statement = self.prepare(sql)?;
+        for (index, param) in params.iter().enumerate() {
+            let index = index as c_int + 1;
+            let code = match param {
+                Param::Int(value) => unsafe { sqlite3_bind_int64(statement.0, index, *value) },
+                Param::Text(text) => unsafe {
+                    sqlite3_bind_text(
+                        statement.0,
+                        index,
+                        text.as_ptr().cast(),
+                        text.len() as c_int,
+                        SQLITE_TRANSIENT,
+                    )
+                },
+            };
+            if code != SQLITE_OK {
+                return Err(self.error(code));
+            }
+        }
+        loop {
+            match unsafe { sqlite3_step(statement.0) } {
+                SQLITE_ROW => row(&Row(statement.0)),
+                SQLITE_DONE => return Ok(()),
+                code => return Err(self.error(code)),
+            }
+        }
+    }
+
+    fn execute(&self, sql: &str, params: &[Param]) -> Result<(), Error> {
+        self.query(sql, params, |_| {})
+    }
+
+    fn prepare(&self, sql: &str) -> Result<Statement, Error> {
+        let mut statement = std::ptr::null_mut();
+        let code = unsafe {
+            sqlite3_prepare_v2(self.0, sql.as_ptr().cast(), sql.len() as c_int, &mut statement, std::ptr::null_mut())
+        };
+        if code != SQLITE_OK {
+            return Err(self.error(code));
+        }
+        Ok(Statement(statement))
+    }
+
+    // BEGIN IMMEDIATE toma el lock de escritura al empezar: quien lee y despues escribe no se cruza con otro
+    fn transaction(&self) -> Result<Transaction<'_>, Error> {
+        self.execute("BEGIN IMMEDIATE", &[])?;
+        Ok(Transaction {
+            connection: self,
+            committed: false,
+        })
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { sqlite3_close_v2(self.0) };
+    }
+}
+
+struct Statement(*mut sqlite3_stmt);
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        unsafe { sqlite3_finalize(self.0) };
+    }
+}
+
+// la fila actual de una consulta; solo vale dentro del callback de query
+struct Row(*mut sqlite3_stmt);
+
+impl Row {
+    fn int(&self, column: c_int) -> i64 {
+        unsafe { sqlite3_column_int64(self.0, column) }
+    }
+
+    fn text(&self, column: c_int) -> String {
+        unsafe {
+            let text = sqlite3_column_text(self.0, column);
+            if text.is_null() {
+                return String::new();
+            }
+            let len = sqlite3_column_bytes(self.0, column) as usize;
+            String::from_utf8_lossy(std::slice::from_raw_parts(text, len)).into_owned()
+        }
+    }
+}
+
+// si no se llega a commit (un error, o un panic en el medio) se deshace todo al soltarla
+struct Transaction<'c> {
+    connection: &'c Connection,
+    committed: bool,
+}
+
+impl Transaction<'_> {
+    fn commit(mut self) -> Result<(), Error> {
+        self.connection.execute("COMMIT", &[])?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for Transaction<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            if let Err(e) = self.connection.execute("ROLLBACK", &[]) {
+                tracing::error!(error = %e, "failed to roll back");
+            }
+        }
+    }
+}
+
+// la base no respondio: ver el comentario del modulo
+fn failed(error: Error) -> ! {
+    tracing::error!(%error, "database error");
+    panic!("database error: {error}")
+}
+
+// el archivo de la base de una DATABASE_URL, sqlite:todos.db o sqlite://todos.db
+pub fn database_path(url: &str) -> Result<PathBuf, String> {
+    let path = url
+        .strip_prefix(URL_SCHEME)
+        .ok_or_else(|| format!("unsupported DATABASE_URL {url:?}, expected {URL_SCHEME}<file>"))?;
+    let path = path.strip_prefix("//").unwrap_or(path);
+    if path.is_empty() {
+        return Err(format!("DATABASE_URL {url:?} has no file"));
+    }
+    Ok(PathBuf::from(path))
+}
+
+// la base del usuario user, al lado de la de DATABASE_URL: data/todos.db -> data/todos.<user>.db
+pub fn tenant_path(database: &Path, user: &str) -> PathBuf {
+    let stem = database.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let name = match database.extension() {
+        Some(extension) => format!("{stem}.{user}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{user}"),
+    };
+    database.with_file_name(name)
+}
+
+fn parse(json: &str) -> Todo {
+    serde_json::from_str(json).unwrap_or_else(|e| {
+        failed(Error {
+            code: 0,
+            message: format!("stored todo is not valid JSON: {e}"),
+        })
+    })
+}
+
+fn to_json(todo: &Todo) -> String {
+    serde_json::to_string(todo).expect("a todo always serializes")
+}
+
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /*
+    Abre (o crea) la base y corre las migraciones que falten. Como persistence::load, un error al arrancar
+    se devuelve en vez de seguir con un store vacio.
+    */
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let describe = |e: Error| format!("failed to open database {}: {e}", path.display());
+        let connection = Connection::open(path).map_err(describe)?;
+        Self::migrate(&connection).map_err(describe)?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    // la transaccion hace que dos instancias que arrancan a la vez no corran la misma migracion dos veces
+    fn migrate(connection: &Connection) -> Result<(), Error> {
+        let transaction = connection.transaction()?;
+        let mut applied = 0;
+        transaction.query("PRAGMA user_version", &[], |row| applied = row.int(0))?;
+        if applied as usize > MIGRATIONS.len() {
+            return Err(Error {
+                code: 0,
+                message: format!("the database is at version {applied}, newer than this server"),
+            });
+        }
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+            tracing::info!(version = index + 1, "running database migration");
+            for statement in *migration {
+                transaction.execute(statement, &[])?;
+            }
+        }
+        // PRAGMA no acepta parametros
+        transaction.execute(&format!("PRAGMA user_version = {}", MIGRATIONS.len()), &[])?;
+        transaction.commit()
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // el id mas alto guardado, para seguir la numeracion de NEXT_ID al abrir la base de un usuario
+    pub fn max_id(&self) -> Option<i64> {
+        let mut max_id = None;
+        self.connection()
+            .query("SELECT id FROM todos ORDER BY id DESC LIMIT 1", &[], |row| max_id = Some(row.int(0)))
+            .unwrap_or_else(|e| failed(e));
+        max_id
+    }
+
+    fn select(connection: &Connection, id: i64) -> Result<Option<Todo>, Error> {
+        let mut todo = None;
+        connection.query("SELECT todo FROM todos WHERE id = ?", &[Param::Int(id)], |row| {
+            todo = Some(parse(&row.text(0)))
+        })?;
+        Ok(todo)
+    }
+
+    fn write(connection: &Connection, todo: &Todo) -> Result<(), Error> {
+        connection.execute(
+            "INSERT OR REPLACE INTO todos (id, version, todo) VALUES (?, ?, ?)",
+            &[Param::Int(todo.id), Param::Int(todo.version as i64), Param::Text(&to_json(todo))],
+        )
+    }
+
+    // como MemoryStore::changed, last_changed no baja aunque el reloj de otra instancia este atrasado
+    fn changed(connection: &Connection) -> Result<(), Error> {
+        connection.execute(
+            "UPDATE store SET last_changed = MAX(last_changed, ?)",
+            &[Param::Int(Utc::now().timestamp_millis())],
+        )
+    }
+}
+
+impl TodoStore for SqliteStore {
+    fn list(&self) -> BoxFuture<'_, Vec<Todo>> {
+        Box::pin(async move {
+            let mut todos = Vec::new();
+            self.connection()
+                .query("SELECT todo FROM todos ORDER BY id", &[], |row| todos.push(parse(&row.text(0))))
+                .unwrap_or_else(|e| failed(e));
+            todos
+        })
+    }
+
+    fn get(&self, id: i64) -> BoxFuture<'_, Option<Todo>> {
+        Box::pin(async move { Self::select(&self.connection(), id).unwrap_or_else(|e| failed(e)) })
+    }
+
+    fn create(&self, todo: Todo) -> BoxFuture<'_, Result<Todo, StoreError>> {
+        Box::pin(async move {
+            let connection = self.connection();
+            let transaction = connection.transaction().unwrap_or_else(|e| failed(e));
+            let inserted = transaction.execute(
+                "INSERT INTO todos (id, version, todo) VALUES (?, ?, ?)",
+                &[Param::Int(todo.id), Param::Int(todo.version as i64), Param::Text(&to_json(&todo))],
+            );
+            match inserted {
+                Ok(()) => {}
+                Err(e) if e.code == SQLITE_CONSTRAINT => return Err(StoreError::IdTaken(todo.id)),
+                Err(e) => failed(e),
+            }
+            Self::changed(&transaction)
+                .and_then(|()| transaction.commit())
+                .unwrap_or_else(|e| failed(e));
+            Ok(todo)
+        })
+    }
+
+    fn update(&self, mut todo: Todo) -> BoxFuture<'_, Result<Todo, StoreError>> {
+        Box::pin(async move {
+            let connection = self.connection();
+            let transaction = connection.transaction().unwrap_or_else(|e| failed(e));
+            let Some(stored) = Self::select(&transaction, todo.id).unwrap_or_else(|e| failed(e)) else {
+                return Err(StoreError::NotFound(todo.id));
+            };
+            if stored.version != todo.version {
+                return Err(StoreError::Conflict(Box::new(stored)));
+            }
+            todo.version += 1;
+            Self::write(&transaction, &todo)
+                .and_then(|()| Self::changed(&transaction))
+                .and_then(|()| transaction.commit())
+                .unwrap_or_else(|e| failed(e));
+            Ok(todo)
+        })
+    }
+
+    fn delete(&self, id: i64) -> BoxFuture<'_, Option<Todo>> {
+        Box::pin(async move {
+            let connection = self.connection();
+            let transaction = connection.transaction().unwrap_or_else(|e| failed(e));
+            let todo = Self::select(&transaction, id).unwrap_or_else(|e| failed(e))?;
+            transaction
+                .execute("DELETE FROM todos WHERE id = ?", &[Param::Int(id)])
+                .and_then(|()| Self::changed(&transaction))
+                .and_then(|()| transaction.commit())
+                .unwrap_or_else(|e| failed(e));
+            Some(todo)
+        })
+    }
+
+    fn last_changed(&self) -> BoxFuture<'_, DateTime<Utc>> {
+        Box::pin(async move {
+            let mut millis = 0;
+            self.connection()
+                .query("SELECT last_changed FROM store", &[], |row| millis = row.int(0))
+                .unwrap_or_else(|e| failed(e));
+            DateTime::from_timestamp_millis(millis).unwrap_or_default()
+        })
+    }
+
+    // lee todos los todos, corre change y escribe solo las filas que cambiaron, todo en una transaccion
+    fn modify_many<'a>(&'a self, change: Change<'a>) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let connection = self.connection();
+            let transaction = connection.transaction().unwrap_or_else(|e| failed(e));
+            let mut before: HashMap<i64, String> = HashMap::new();
+            transaction
+                .query("SELECT id, todo FROM todos", &[], |row| {
+                    before.insert(row.int(0), row.text(1));
+                })
+                .unwrap_or_else(|e| failed(e));
+            let mut todos: HashMap<i64, Todo> = before.iter().map(|(id, json)| (*id, parse(json))).collect();
+            change(&mut todos);
+
+            let mut changed = false;
+            for id in before.keys().filter(|id| !todos.contains_key(id)) {
+                transaction
+                    .execute("DELETE FROM todos WHERE id = ?", &[Param::Int(*id)])
+                    .unwrap_or_else(|e| failed(e));
+                changed = true;
+            }
+            for todo in todos.values() {
+                // comparamos el JSON: leer y volver a escribir un todo da el mismo texto
+                if before.get(&todo.id).is_some_and(|json| *json == to_json(todo)) {
+                    continue;
+                }
+                Self::write(&transaction, todo).unwrap_or_else(|e| failed(e));
+                changed = true;
+            }
+            if changed {
+                Self::changed(&transaction).unwrap_or_else(|e| failed(e));
+            }
+            transaction.commit().unwrap_or_else(|e| failed(e));
+        })
+    }
+}
@@ -0,0 +1,250 @@
+/*
+Abstraccion del almacenamiento: los handlers hablan con el trait TodoStore en vez de tocar el HashMap,
+asi se puede agregar otro backend (SQLite, Redis) sin cambiar su logica. MemoryStore es el de siempre:
+un HashMap en memoria detras de un RwLock, que se baja a disco con persistence despues de cada cambio.
+
+update es un compare-and-swap sobre la version del todo (como un UPDATE ... WHERE version = ? en SQL),
+asi dos requests que leyeron la misma version no se pisan aunque el trait no tenga transacciones.
+
+cached_get es el get de GET /todos/<id>, con un cache LRU adelante pensado para cuando el backend sea
+una base de datos. En MemoryStore el cache se llena con el read lock tomado y se invalida con el write lock,
+asi nunca queda guardada una version vieja de un todo.
+
+last_changed es cuando cambio el store por ultima vez, contando los borrados: el Last-Modified de GET /todos.
+
+Las operaciones que cambian varios todos de una vez (batch, move, archive, borrado en cascada, import) van por
+modify_many, que les da el mapa entero sin que nadie mas lea ni escriba en el medio, como una transaccion.
+*/
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use lru::LruCache;
+use tokio::sync::RwLock;
+
+use crate::models::Todo;
+use crate::persistence;
+
+#[derive(Debug)]
+pub enum StoreError {
+    // no hay un todo con ese id
+    NotFound(i64),
+    // create con un id que ya existe
+    IdTaken(i64),
+    // update sobre una version vieja; trae el todo como esta guardado
+    Conflict(Box<Todo>),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NotFound(id) => write!(f, "todo {id} not found"),
+            StoreError::IdTaken(id) => write!(f, "todo {id} already exists"),
+            StoreError::Conflict(todo) => write!(f, "todo {} is at version {}", todo.id, todo.version),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+// el cambio que recibe modify_many
+pub type Change<'a> = Box<dyn FnOnce(&mut HashMap<i64, Todo>) + Send + 'a>;
+
+/*
+Devuelven BoxFuture para que el trait se pueda usar como dyn TodoStore.
+Los metodos ven todos los todos, tambien los borrados logicamente y los archivados: filtrar es cosa de los handlers.
+*/
+pub trait TodoStore: Send + Sync {
+    fn list(&self) -> BoxFuture<'_, Vec<Todo>>;
+
+    fn get(&self, id: i64) -> BoxFuture<'_, Option<Todo>>;
+
+    // guarda un todo nuevo con el id que ya trae
+    fn create(&self, todo: Todo) -> BoxFuture<'_, Result<Todo, StoreError>>;
+
+    // reemplaza el todo si la version guardada es todo.version; el guardado queda con la version siguiente
+    fn update(&self, todo: Todo) -> BoxFuture<'_, Result<Todo, StoreError>>;
+
+    // borra el todo definitivamente y lo devuelve
+    fn delete(&self, id: i64) -> BoxFuture<'_, Option<Todo>>;
+
+    // como get, pero puede salir de un cache; el bool dice si fue un acierto del cache
+    fn cached_get(&self, id: i64) -> BoxFuture<'_, (Option<Todo>, bool)> {
+        Box::pin(async move { (self.get(id).await, false) })
+    }
+
+    // la ultima vez que cambio algun todo, o se agrego o se saco uno; no baja nunca
+    fn last_changed(&self) -> BoxFuture<'_, DateTime<Utc>>;
+
+    // corre change sobre todos los todos de forma atomica y guarda lo que haya cambiado
+    fn modify_many<'a>(&'a self, change: Change<'a>) -> BoxFuture<'a, ()>;
+
+    // al apagar el servidor; un backend que escribe cada cambio al momento no tiene nada que hacer
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+impl dyn TodoStore + '_ {
+    // modify_many para los handlers: devuelve lo que devuelve change, por ejemplo el error con el que responder
+    pub async fn transaction<R: Send>(&self, change: impl FnOnce(&mut HashMap<i64, Todo>) -> R + Send) -> R {
+        let mut result = None;
+        self.modify_many(Box::new(|todos| result = Some(change(todos)))).await;
+        result.expect("modify_many runs the change")
+    }
+}
+
+// cuantos todos guarda el cache de cached_get si no se define GET_CACHE_CAPACITY (0 lo apaga)
+pub const DEFAULT_GET_CACHE_CAPACITY: usize = 1024;
+
+// capacidad del cache de cached_get, de GET_CACHE_CAPACITY
+pub fn cache_capacity() -> usize {
+    match std::env::var("GET_CACHE_CAPACITY") {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid GET_CACHE_CAPACITY {value:?}: {e}")),
+        Err(_) => DEFAULT_GET_CACHE_CAPACITY,
+    }
+}
+
+pub struct MemoryStore {
+    todos: RwLock<HashMap<i64, Todo>>,
+    cache: Option<Mutex<LruCache<i64, Todo>>>,
+    // arranca en el momento de la carga: no sabemos que se borro antes de un reinicio
+    last_changed: Mutex<DateTime<Utc>>,
+}
+
+impl MemoryStore {
+    pub fn new(todos: HashMap<i64, Todo>) -> Self {
+        MemoryStore {
+            todos: RwLock::new(todos),
+            cache: None,
+            last_changed: Mutex::new(Utc::now()),
+        }
+    }
+
+    // prende el cache de cached_get con lugar para capacity todos; con 0 queda apagado
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity)));
+        self
+    }
+
+    // arranca con lo que haya guardado en disco, o vacio si todavia no hay archivo
+    pub fn load() -> Self {
+        Self::new(persistence::load()).with_cache(cache_capacity())
+    }
+
+    fn cache(&self) -> Option<std::sync::MutexGuard<'_, LruCache<i64, Todo>>> {
+        self.cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    // se llama con el write lock tomado, despues de cambiar el todo
+    fn invalidate(&self, id: i64) {
+        if let Some(mut cache) = self.cache() {
+            cache.pop(&id);
+        }
+        self.changed();
+    }
+
+    // con el write lock tomado, asi quien lee last_changed antes que los todos nunca ve un cambio sin su fecha
+    fn changed(&self) {
+        let mut last_changed = self.last_changed.lock().unwrap_or_else(|e| e.into_inner());
+        *last_changed = (*last_changed).max(Utc::now());
+    }
+}
+
+impl TodoStore for MemoryStore {
+    fn list(&self) -> BoxFuture<'_, Vec<Todo>> {
+        Box::pin(async move { self.todos.read().await.values().cloned().collect() })
+    }
+
+    fn get(&self, id: i64) -> BoxFuture<'_, Option<Todo>> {
+        Box::pin(async move { self.todos.read().await.get(&id).cloned() })
+    }
+
+    fn create(&self, todo: Todo) -> BoxFuture<'_, Result<Todo, StoreError>> {
+        Box::pin(async move {
+            let mut todos = self.todos.write().await;
+            if todos.contains_key(&todo.id) {
+                return Err(StoreError::IdTaken(todo.id));
+            }
+            todos.insert(todo.id, todo.clone());
+            self.invalidate(todo.id);
+            persistence::save(&todos);
+            Ok(todo)
+        })
+    }
+
+    fn update(&self, mut todo: Todo) -> BoxFuture<'_, Result<Todo, StoreError>> {
+        Box::pin(async move {
+            let mut todos = self.todos.write().await;
+            let Some(stored) = todos.get_mut(&todo.id) else {
+                return Err(StoreError::NotFound(todo.id));
+            };
+            if stored.version != todo.version {
+                return Err(StoreError::Conflict(Box::new(stored.clone())));
+            }
+            todo.version += 1;
+            *stored = todo.clone();
+            self.invalidate(todo.id);
+            persistence::save(&todos);
+            Ok(todo)
+        })
+    }
+
+    fn delete(&self, id: i64) -> BoxFuture<'_, Option<Todo>> {
+        Box::pin(async move {
+            let mut todos = self.todos.write().await;
+            let todo = todos.remove(&id)?;
+            self.invalidate(id);
+            persistence::save(&todos);
+            Some(todo)
+        })
+    }
+
+    fn last_changed(&self) -> BoxFuture<'_, DateTime<Utc>> {
+        Box::pin(async move { *self.last_changed.lock().unwrap_or_else(|e| e.into_inner()) })
+    }
+
+    fn modify_many<'a>(&'a self, change: Change<'a>) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let mut todos = self.todos.write().await;
+            change(&mut todos);
+            // no sabemos que todos cambio, asi que vaciamos todo el cache; nadie lo puede volver a llenar
+            // hasta que se suelte el write lock
+            if let Some(mut cache) = self.cache() {
+                cache.clear();
+            }
+            self.changed();
+            persistence::save(&todos);
+        })
+    }
+
+    // persistence ya guarda cada cambio; lo repetimos por si algun guardado fallo
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move { persistence::save(&*self.todos.read().await) })
+    }
+
+    fn cached_get(&self, id: i64) -> BoxFuture<'_, (Option<Todo>, bool)> {
+        Box::pin(async move {
+            let todos = self.todos.read().await;
+            let Some(mut cache) = self.cache() else {
+                return (todos.get(&id).cloned(), false);
+            };
+            if let Some(todo) = cache.get(&id) {
+                return (Some(todo.clone()), true);
+            }
+            // los ids que no existen no se guardan en el cache
+            let todo = todos.get(&id).cloned();
+            if let Some(todo) = &todo {
+                cache.put(id, todo.clone());
+            }
+            (todo, false)
+        })
+    }
+}
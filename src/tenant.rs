@@ -0,0 +1,60 @@
+/*
+Modo multi-tenant: con MULTI_TENANT=true cada request a /todos o /graphql tiene que mandar X-User-Id y solo ve
+los todos de ese usuario, que se guardan en su propio archivo al lado de TODOS_FILE (todos.<usuario>.json), o en su
+propia base al lado de la de DATABASE_URL (todos.<usuario>.db).
+El usuario de la request en curso y su store viven en un task local que pone el hoop middleware::tenant_scope, asi
+los handlers siguen usando store(), persistence y events sin saber de usuarios. Sin la variable hay un solo store,
+como siempre.
+*/
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::store::TodoStore;
+
+pub const USER_ID_HEADER: &str = "x-user-id";
+
+// largo maximo de un X-User-Id
+pub const MAX_USER_ID_LEN: usize = 64;
+
+struct Tenant {
+    user: String,
+    store: Arc<dyn TodoStore>,
+}
+
+tokio::task_local! {
+    static CURRENT: Tenant;
+}
+
+// MULTI_TENANT se lee en cada request, como DEV_MODE, para poder prenderlo y apagarlo en los tests
+pub fn enabled() -> bool {
+    std::env::var("MULTI_TENANT").is_ok_and(|value| value == "true")
+}
+
+// usuario de la request en curso; None si no hay modo multi-tenant o fuera de una request
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|tenant| tenant.user.clone()).ok()
+}
+
+// store del usuario de la request en curso, el que le paso tenant_scope a scope
+pub fn current_store() -> Option<Arc<dyn TodoStore>> {
+    CURRENT.try_with(|tenant| tenant.store.clone()).ok()
+}
+
+// corre future como una request del usuario user, sobre su store
+pub async fn scope<F: Future>(user: String, store: Arc<dyn TodoStore>, future: F) -> F::Output {
+    CURRENT.scope(Tenant { user, store }, future).await
+}
+
+// el id termina siendo parte de un nombre de archivo, asi que solo aceptamos letras, numeros, - y _
+pub fn validate_user_id(user: &str) -> Result<(), String> {
+    if user.is_empty() {
+        return Err("the X-User-Id header is required".to_string());
+    }
+    if user.len() > MAX_USER_ID_LEN {
+        return Err(format!("X-User-Id must be at most {MAX_USER_ID_LEN} characters"));
+    }
+    if !user.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("X-User-Id may only contain letters, digits, - and _".to_string());
+    }
+    Ok(())
+}
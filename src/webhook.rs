@@ -0,0 +1,173 @@
+/*
+Aviso de vencimientos: con WEBHOOK_URL definida, una tarea de fondo revisa cada WEBHOOK_INTERVAL_SECS los todos
+pendientes cuya due_date ya paso y por cada uno manda un POST con un OverdueNotification en JSON.
+Cada todo se avisa una sola vez por due_date: si se completa o le cambian la fecha, sale de la lista y puede
+volver a avisarse cuando venza de nuevo. Solo se avisan los que vencen mientras el servidor corre: la primera
+pasada anota los que ya estaban vencidos sin mandarlos, asi un reinicio no vuelve a avisar todo lo atrasado
+(a cambio, lo que vencio con el servidor apagado no se avisa).
+
+Un envio que falla (error de conexion, timeout o status que no es 2xx) se reintenta hasta WEBHOOK_MAX_ATTEMPTS
+veces con espera creciente; si ninguno sale se loguea y ese todo se da por avisado, para no insistir para siempre
+con un receptor caido. Solo se soporta http://, no hay cliente TLS entre las dependencias.
+*/
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::header::{self, HeaderValue};
+use hyper::{Request, Uri};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use tokio::net::TcpStream;
+
+use crate::models::Todo;
+
+// intentos por aviso si no se define WEBHOOK_MAX_ATTEMPTS
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+// espera antes del primer reintento; se duplica en cada uno
+pub const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+// lo maximo que esperamos a que el receptor conteste un POST
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// el cuerpo de cada POST; user es el dueño del todo en modo multi-tenant
+#[derive(Serialize)]
+pub struct OverdueNotification<'a> {
+    pub event: &'static str,
+    pub user: Option<&'a str>,
+    pub todo: &'a Todo,
+}
+
+pub struct OverdueWebhook {
+    uri: Uri,
+    max_attempts: u32,
+    // (usuario, id) de cada todo avisado -> la due_date por la que se aviso; los ids se repiten entre usuarios
+    notified: HashMap<(Option<String>, i64), DateTime<Utc>>,
+    // false hasta la primera pasada de notify
+    primed: bool,
+}
+
+impl OverdueWebhook {
+    // uri tiene que ser http://host[:port]/path
+    pub fn new(uri: Uri, max_attempts: u32) -> Self {
+        if uri.scheme_str() != Some("http") || uri.host().is_none() {
+            panic!("invalid webhook url {uri}: only http://host[:port]/path is supported");
+        }
+        OverdueWebhook {
+            uri,
+            max_attempts: max_attempts.max(1),
+            notified: HashMap::new(),
+            primed: false,
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("WEBHOOK_URL").ok()?;
+        let uri: Uri = url
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid WEBHOOK_URL {url:?}: {e}"));
+        let max_attempts = match std::env::var("WEBHOOK_MAX_ATTEMPTS") {
+            Ok(value) => value
+                .parse()
+                .unwrap_or_else(|e| panic!("invalid WEBHOOK_MAX_ATTEMPTS {value:?}: {e}")),
+            Err(_) => DEFAULT_MAX_ATTEMPTS,
+        };
+        Some(Self::new(uri, max_attempts))
+    }
+
+    /*
+    Avisa los todos de todos (junto con su usuario) que esten vencidos a now y no se hayan avisado por esa
+    due_date. Devuelve los ids que el receptor acepto. Los avisados que ya no estan vencidos se olvidan,
+    asi la lista no crece mas que los todos vencidos. La primera llamada solo anota los vencidos.
+    */
+    pub async fn notify(&mut self, todos: &[(Option<String>, Todo)], now: DateTime<Utc>) -> Vec<i64> {
+        let overdue: HashMap<(Option<String>, i64), &Todo> = todos
+            .iter()
+            .filter(|(_, todo)| !todo.is_deleted() && todo.is_overdue(now))
+            .map(|(user, todo)| ((user.clone(), todo.id), todo))
+            .collect();
+        self.notified
+            .retain(|key, due_date| overdue.get(key).is_some_and(|todo| todo.due_date == Some(*due_date)));
+        if !self.primed {
+            self.primed = true;
+            for (key, todo) in overdue {
+                if let Some(due_date) = todo.due_date {
+                    self.notified.insert(key, due_date);
+                }
+            }
+            return Vec::new();
+        }
+
+        let mut delivered = Vec::new();
+        for ((user, id), todo) in overdue {
+            // is_overdue asegura que hay due_date
+            let Some(due_date) = todo.due_date else { continue };
+            let key = (user, id);
+            if self.notified.contains_key(&key) {
+                continue;
+            }
+            let notification = OverdueNotification {
+                event: "overdue",
+                user: key.0.as_deref(),
+                todo,
+            };
+            if self.deliver(&notification).await {
+                delivered.push(id);
+            }
+            self.notified.insert(key, due_date);
+        }
+        delivered.sort_unstable();
+        delivered
+    }
+
+    // manda el aviso con hasta max_attempts intentos; false si ninguno salio
+    async fn deliver(&self, notification: &OverdueNotification<'_>) -> bool {
+        let body = serde_json::to_vec(notification).expect("a todo always serializes");
+        let mut backoff = RETRY_BACKOFF;
+        for attempt in 1..=self.max_attempts {
+            match tokio::time::timeout(REQUEST_TIMEOUT, self.post(body.clone())).await {
+                Ok(Ok(())) => return true,
+                Ok(Err(error)) => tracing::warn!(id = notification.todo.id, attempt, %error, "webhook failed"),
+                Err(_) => tracing::warn!(id = notification.todo.id, attempt, "webhook timed out"),
+            }
+            if attempt < self.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+        tracing::error!(id = notification.todo.id, attempts = self.max_attempts, "giving up on overdue webhook");
+        false
+    }
+
+    // un POST por conexion: los avisos son pocos y espaciados, no vale la pena un pool
+    async fn post(&self, body: Vec<u8>) -> Result<(), String> {
+        let host = self.uri.host().expect("new checks the host");
+        let port = self.uri.port_u16().unwrap_or(80);
+        let stream = TcpStream::connect((host, port)).await.map_err(|e| e.to_string())?;
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::spawn(async move {
+            if let Err(e) = conn.await {
+                tracing::debug!(error = %e, "webhook connection closed");
+            }
+        });
+
+        let path = self.uri.path_and_query().map_or("/", |path| path.as_str());
+        let authority = self.uri.authority().expect("new checks the host").as_str();
+        let request = Request::post(path)
+            .header(header::HOST, authority)
+            .header(header::CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| e.to_string())?;
+        let response = sender.send_request(request).await.map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("webhook answered {}", response.status()))
+        }
+    }
+}
@@ -0,0 +1,38 @@
+/*
+Tests del IdempotencyCache suelto: vencimiento de las keys y el tope de keys recordadas.
+*/
+use std::time::Duration;
+
+use serde_json::json;
+use todo_api::idempotency::IdempotencyCache;
+use todo_api::models::Todo;
+
+fn todo(id: i64) -> Todo {
+    serde_json::from_value(json!({ "id": id, "text": "replayed", "completed": false, "version": 1 })).unwrap()
+}
+
+#[test]
+fn keys_expire_after_their_ttl() {
+    let cache = IdempotencyCache::new(Duration::from_secs(60));
+    cache.insert("kept".to_string(), todo(1));
+    cache.insert_for("gone".to_string(), todo(2), Duration::ZERO);
+    assert_eq!(cache.get("kept").unwrap().id, 1);
+    assert!(cache.get("gone").is_none());
+}
+
+#[test]
+fn a_full_cache_forgets_the_oldest_key() {
+    let cache = IdempotencyCache::new(Duration::from_secs(60)).with_max_keys(2);
+    cache.insert("a".to_string(), todo(1));
+    std::thread::sleep(Duration::from_millis(2));
+    cache.insert("b".to_string(), todo(2));
+    // volver a guardar una key que ya esta no saca a otra
+    cache.insert("b".to_string(), todo(2));
+    assert!(cache.get("a").is_some());
+
+    std::thread::sleep(Duration::from_millis(2));
+    cache.insert("c".to_string(), todo(3));
+    assert!(cache.get("a").is_none());
+    assert_eq!(cache.get("b").unwrap().id, 2);
+    assert_eq!(cache.get("c").unwrap().id, 3);
+}
@@ -0,0 +1,98 @@
+/*
+Tests de los middlewares que se pueden probar sueltos, sin el Service completo.
+*/
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use salvo::prelude::*;
+use salvo::test::{ResponseExt, TestClient};
+use todo_api::middleware::{self, RateLimiter, REQUEST_ID_KEY};
+
+const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+#[test]
+fn rate_limiter_spends_the_burst_and_refills() {
+    let limiter = RateLimiter::new(2.0, 3.0);
+    let start = Instant::now();
+    for _ in 0..3 {
+        assert!(limiter.try_acquire(CLIENT, start).is_ok());
+    }
+    // sin tokens, a 2 por segundo el proximo llega en medio segundo
+    assert_eq!(limiter.try_acquire(CLIENT, start), Err(Duration::from_millis(500)));
+    // cada IP tiene su bucket
+    assert!(limiter.try_acquire(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), start).is_ok());
+
+    assert!(limiter.try_acquire(CLIENT, start + Duration::from_millis(500)).is_ok());
+    assert!(limiter.try_acquire(CLIENT, start + Duration::from_millis(500)).is_err());
+    // la recarga no pasa de burst
+    let later = start + Duration::from_secs(60);
+    for _ in 0..3 {
+        assert!(limiter.try_acquire(CLIENT, later).is_ok());
+    }
+    assert!(limiter.try_acquire(CLIENT, later).is_err());
+}
+
+// TestClient no manda direccion remota; la ponemos a mano para que el limiter tenga con que agrupar
+#[handler]
+async fn from_client(req: &mut Request) {
+    *req.remote_addr_mut() = SocketAddr::new(CLIENT, 40000).into();
+}
+
+#[handler]
+async fn ok() -> &'static str {
+    "ok"
+}
+
+#[tokio::test]
+async fn rate_limiter_answers_429_with_retry_after() {
+    let service = Service::new(Router::new().get(ok))
+        .hoop(from_client)
+        .hoop(RateLimiter::new(0.5, 1.0));
+
+    let res = TestClient::get("http://127.0.0.1:5800/").send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let res = TestClient::get("http://127.0.0.1:5800/").send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::TOO_MANY_REQUESTS));
+    // a medio token por segundo faltan 2 segundos para el proximo
+    assert_eq!(res.headers()["retry-after"], "2");
+}
+
+// devuelve el id que request_id dejo en el depot, para compararlo con el header
+#[handler]
+async fn depot_request_id(depot: &mut Depot) -> String {
+    depot.get::<String>(REQUEST_ID_KEY).unwrap().clone()
+}
+
+#[tokio::test]
+async fn request_id_generates_an_id_for_each_request() {
+    let service = Service::new(Router::new().get(depot_request_id)).hoop(middleware::request_id);
+
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let mut res = TestClient::get("http://127.0.0.1:5800/").send(&service).await;
+        let id = res.headers()["x-request-id"].to_str().unwrap().to_string();
+        assert!(uuid::Uuid::parse_str(&id).is_ok(), "{id}");
+        assert_eq!(res.take_string().await.unwrap(), id);
+        ids.push(id);
+    }
+    assert_ne!(ids[0], ids[1]);
+
+    // un X-Request-Id vacio cuenta como si no lo hubiera mandado
+    let res = TestClient::get("http://127.0.0.1:5800/")
+        .add_header("x-request-id", "", true)
+        .send(&service)
+        .await;
+    assert!(uuid::Uuid::parse_str(res.headers()["x-request-id"].to_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn request_id_echoes_the_client_id() {
+    let service = Service::new(Router::new().get(depot_request_id)).hoop(middleware::request_id);
+
+    let mut res = TestClient::get("http://127.0.0.1:5800/")
+        .add_header("x-request-id", "trace-abc-123", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.headers()["x-request-id"], "trace-abc-123");
+    assert_eq!(res.take_string().await.unwrap(), "trace-abc-123");
+}
@@ -0,0 +1,146 @@
+/*
+Tests del trait TodoStore sobre un store suelto, sin el Service: el contrato que tiene que cumplir cualquier backend,
+corrido sobre MemoryStore y sobre SqliteStore. Tambien el resumen del store que loguea log_store_stats.
+*/
+use std::collections::HashMap;
+
+use serde_json::json;
+use todo_api::models::Todo;
+use todo_api::sqlite::{database_path, tenant_path, SqliteStore};
+use todo_api::store::{MemoryStore, StoreError, TodoStore};
+use todo_api::store_stats;
+
+fn todo(id: i64, text: &str) -> Todo {
+    serde_json::from_value(json!({ "id": id, "text": text, "completed": false, "version": 1 })).unwrap()
+}
+
+fn memory_store() -> MemoryStore {
+    // los cambios se guardan en TODOS_FILE; que no sea el todos.json del directorio de trabajo
+    let file = std::env::temp_dir().join(format!("todo_api_store_test_{}.json", std::process::id()));
+    std::env::set_var("TODOS_FILE", file);
+    MemoryStore::new(HashMap::new()).with_cache(8)
+}
+
+async fn check_crud(store: &dyn TodoStore) {
+    assert!(store.list().await.is_empty());
+    store.create(todo(1, "first")).await.unwrap();
+    assert!(matches!(store.create(todo(1, "again")).await, Err(StoreError::IdTaken(1))));
+    assert_eq!(store.get(1).await.unwrap().text, "first");
+
+    // el update es un compare-and-swap sobre la version
+    let mut changed = todo(1, "changed");
+    let updated = store.update(changed.clone()).await.unwrap();
+    assert_eq!(updated.version, 2);
+    changed.text = "stale".to_string();
+    match store.update(changed).await {
+        Err(StoreError::Conflict(stored)) => assert_eq!(stored.text, "changed"),
+        other => panic!("expected a conflict, got {other:?}"),
+    }
+    assert!(matches!(store.update(todo(2, "missing")).await, Err(StoreError::NotFound(2))));
+
+    // el cache nunca devuelve una version vieja
+    let (cached, hit) = store.cached_get(1).await;
+    assert_eq!((cached.unwrap().version, hit), (updated.version, false));
+    store.update(updated).await.unwrap();
+    assert_eq!(store.cached_get(1).await.0.unwrap().version, 3);
+
+    assert_eq!(store.delete(1).await.unwrap().text, "changed");
+    assert!(store.delete(1).await.is_none());
+    assert!(store.get(1).await.is_none());
+}
+
+async fn check_modify_many(store: &dyn TodoStore) {
+    store.create(todo(1, "one")).await.unwrap();
+    store.create(todo(2, "two")).await.unwrap();
+    let before = store.last_changed().await;
+
+    // lo que devuelve change sale de transaction, y los cambios quedan todos juntos
+    let removed = store
+        .transaction(|todos| {
+            let removed = todos.remove(&1);
+            todos.get_mut(&2).unwrap().text = "second".to_string();
+            todos.insert(3, todo(3, "three"));
+            removed
+        })
+        .await;
+    assert_eq!(removed.unwrap().text, "one");
+    let mut texts: Vec<String> = store.list().await.into_iter().map(|todo| todo.text).collect();
+    texts.sort();
+    assert_eq!(texts, ["second", "three"]);
+    assert_eq!(store.cached_get(2).await.0.unwrap().text, "second");
+    assert!(store.last_changed().await >= before);
+
+    // vaciar el store es una transaccion mas
+    store.transaction(|todos| todos.clear()).await;
+    assert!(store.list().await.is_empty());
+}
+
+#[tokio::test]
+async fn memory_store_crud() {
+    check_crud(&memory_store()).await;
+}
+
+#[tokio::test]
+async fn memory_store_modify_many() {
+    check_modify_many(&memory_store()).await;
+}
+
+// cada test con su base, asi corren en paralelo sin pisarse
+fn sqlite_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("todo_api_store_test_{}_{name}.db", std::process::id()));
+    std::fs::remove_file(&path).ok();
+    path
+}
+
+#[tokio::test]
+async fn sqlite_store_crud() {
+    let path = sqlite_path("crud");
+    check_crud(&SqliteStore::open(&path).unwrap()).await;
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn sqlite_store_modify_many() {
+    let path = sqlite_path("modify_many");
+    check_modify_many(&SqliteStore::open(&path).unwrap()).await;
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn sqlite_store_keeps_todos_across_reopens() {
+    let path = sqlite_path("reopen");
+    let store = SqliteStore::open(&path).unwrap();
+    store.create(todo(7, "kept")).await.unwrap();
+    let last_changed = store.last_changed().await;
+    drop(store);
+
+    // las migraciones ya corridas no se repiten y la fecha del ultimo cambio queda en la base
+    let store = SqliteStore::open(&path).unwrap();
+    assert_eq!(store.get(7).await.unwrap().text, "kept");
+    assert_eq!(store.max_id(), Some(7));
+    assert_eq!(store.last_changed().await, last_changed);
+    drop(store);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn database_url_names_a_sqlite_file() {
+    assert_eq!(database_path("sqlite:todos.db").unwrap(), std::path::Path::new("todos.db"));
+    assert_eq!(database_path("sqlite://data/todos.db").unwrap(), std::path::Path::new("data/todos.db"));
+    assert!(database_path("postgres://localhost/todos").is_err());
+    assert!(database_path("sqlite:").is_err());
+    assert_eq!(tenant_path(std::path::Path::new("data/todos.db"), "ana"), std::path::Path::new("data/todos.ana.db"));
+}
+
+#[test]
+fn store_stats_skip_deleted_todos() {
+    assert_eq!(store_stats(&[]), (0, 0, 0.0));
+
+    let mut done = todo(2, "done");
+    done.completed = true;
+    let mut deleted = todo(3, "deleted");
+    deleted.completed = true;
+    deleted.deleted_at = Some(chrono::Utc::now());
+    let todos = [todo(1, "pending"), done, deleted, todo(4, "also pending")];
+    assert_eq!(store_stats(&todos), (3, 1, 1.0 / 3.0));
+}
@@ -0,0 +1,2291 @@
+/*
+Tests de integracion: levantan el Service completo con TestClient y recorren el CRUD de punta a punta.
+El STORE es global, asi que cada test toma LOCK y vacia el store antes de empezar.
+*/
+use salvo::prelude::*;
+use salvo::test::{ResponseExt, TestClient};
+use serde_json::{json, Value};
+use tokio::sync::{Mutex, MutexGuard};
+
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+fn url(path: &str) -> String {
+    format!("http://127.0.0.1:5800{path}")
+}
+
+async fn setup() -> (MutexGuard<'static, ()>, Service) {
+    let guard = LOCK.lock().await;
+    // los tests escriben en un archivo temporal y no en el todos.json del directorio de trabajo
+    let file = std::env::temp_dir().join(format!("todo_api_test_{}.json", std::process::id()));
+    std::env::set_var("TODOS_FILE", file);
+
+    let service = todo_api::service();
+    let res = TestClient::delete(url("/todos"))
+        .query("confirm", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    (guard, service)
+}
+
+async fn create(service: &Service, text: &str) -> Value {
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": text }))
+        .send(service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(res.headers()["location"], format!("/todos/{}", todo["id"]));
+    todo
+}
+
+#[tokio::test]
+async fn crud_lifecycle() {
+    let (_guard, service) = setup().await;
+
+    let created = create(&service, "buy milk").await;
+    let id = created["id"].as_i64().unwrap();
+    assert_eq!(created["text"], "buy milk");
+    assert_eq!(created["completed"], false);
+
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0]["id"], id);
+
+    let mut res = TestClient::get(url(&format!("/todos/{id}"))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["text"], "buy milk");
+
+    let mut res = TestClient::put(url(&format!("/todos/{id}")))
+        .json(&json!({ "id": id, "text": "buy oat milk", "completed": false, "version": 1 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let etag = res.headers()["etag"].clone();
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["text"], "buy oat milk");
+    assert_eq!(todo["version"], 2);
+    assert_eq!(etag, format!("\"{id}-2\""));
+
+    let mut res = TestClient::patch(url(&format!("/todos/{id}")))
+        .json(&json!({ "completed": true, "version": 2 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["text"], "buy oat milk");
+    assert_eq!(todo["completed"], true);
+
+    let mut res = TestClient::post(url(&format!("/todos/{id}/toggle")))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["completed"], false);
+
+    let res = TestClient::delete(url(&format!("/todos/{id}"))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+
+    let res = TestClient::get(url(&format!("/todos/{id}"))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn batch_with_duplicate_id_is_rejected() {
+    let (_guard, service) = setup().await;
+
+    let created = create(&service, "existing").await;
+    let id = created["id"].as_i64().unwrap();
+
+    let mut res = TestClient::post(url("/todos/batch"))
+        .json(&json!([
+            { "id": id, "text": "clash", "completed": false },
+            { "id": id + 100, "text": "new", "completed": false },
+        ]))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body["ids"], json!([id]));
+
+    // todo o nada: el que no chocaba tampoco se inserto
+    let res = TestClient::get(url(&format!("/todos/{}", id + 100))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+    // los campos que maneja el servidor se ignoran
+    let mut res = TestClient::post(url("/todos/batch"))
+        .json(&json!([{
+            "id": id + 100, "text": "new", "completed": false, "created_at": "2000-01-01T00:00:00Z",
+            "deleted_at": "2000-01-01T00:00:00Z", "archived": true, "version": 42,
+        }]))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    let todos: Vec<Value> = res.take_json().await.unwrap();
+    assert_ne!(todos[0]["created_at"], "2000-01-01T00:00:00Z");
+    assert!(todos[0]["deleted_at"].is_null());
+    assert_eq!(todos[0]["archived"], false);
+    assert_eq!(todos[0]["version"], 1);
+}
+
+#[tokio::test]
+async fn update_and_delete_unknown_id_return_not_found() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::put(url("/todos/999"))
+        .json(&json!({ "id": 999, "text": "ghost", "completed": false }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body["code"], 404);
+
+    let res = TestClient::delete(url("/todos/999")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+    // un id que no es un numero no es una ruta; uno que no entra en un i64 es un 400
+    let mut res = TestClient::get(url("/todos/abc")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body["path"], "/todos/abc");
+    let res = TestClient::get(url("/todos/99999999999999999999")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn invalid_body_returns_bad_request() {
+    let (_guard, service) = setup().await;
+
+    let res = TestClient::post(url("/todos"))
+        .raw_json("{not json")
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+
+    // un text con solo espacios queda vacio despues de normalizar
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "   " }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+
+    // el error de serde llega en detail para que el cliente sepa que campo esta mal
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "completed": true }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    let error: Value = res.take_json().await.unwrap();
+    assert_eq!(error["message"], "invalid request body");
+    assert!(error["detail"].as_str().unwrap().contains("missing field `text`"));
+}
+
+#[tokio::test]
+async fn count_reports_totals_by_status() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "one").await;
+    let done = create(&service, "two").await;
+    TestClient::post(url(&format!("/todos/{}/toggle", done["id"])))
+        .send(&service)
+        .await;
+
+    let mut res = TestClient::get(url("/todos/count")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let counts: Value = res.take_json().await.unwrap();
+    assert_eq!(counts, json!({ "total": 2, "completed": 1, "pending": 1 }));
+}
+
+#[tokio::test]
+async fn stats_group_by_priority() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::get(url("/todos/stats")).send(&service).await;
+    let stats: Value = res.take_json().await.unwrap();
+    assert_eq!(stats, json!({ "total": 0, "completed": 0, "pending": 0 }));
+
+    for (text, priority) in [("a", Some(2)), ("b", Some(2)), ("c", Some(5)), ("d", None)] {
+        TestClient::post(url("/todos"))
+            .json(&json!({ "text": text, "priority": priority }))
+            .send(&service)
+            .await;
+    }
+
+    let mut res = TestClient::get(url("/todos/stats")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let stats: Value = res.take_json().await.unwrap();
+    assert_eq!(
+        stats,
+        json!({ "total": 4, "completed": 0, "pending": 4, "by_priority": { "2": 2, "5": 1 } })
+    );
+}
+
+#[tokio::test]
+async fn put_with_upsert_creates_missing_todo() {
+    let (_guard, service) = setup().await;
+
+    let body = json!({ "id": 0, "text": "upserted", "completed": false });
+
+    let res = TestClient::put(url("/todos/4242")).json(&body).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+    let mut res = TestClient::put(url("/todos/4242"))
+        .query("upsert", true)
+        .json(&body)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["id"], 4242);
+    assert_eq!(res.headers()["location"], "/todos/4242");
+
+    // si ya existe es un reemplazo comun y necesita la version
+    let mut res = TestClient::put(url("/todos/4242"))
+        .query("upsert", true)
+        .json(&json!({ "id": 0, "text": "upserted", "completed": false, "version": 1 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    assert!(res.headers().contains_key("etag"));
+    assert_eq!(res.take_json::<Value>().await.unwrap()["version"], 2);
+}
+
+#[tokio::test]
+async fn list_sorts_and_filters_by_priority() {
+    let (_guard, service) = setup().await;
+
+    for (text, priority) in [("low", json!(1)), ("none", Value::Null), ("high", json!(5))] {
+        let res = TestClient::post(url("/todos"))
+            .json(&json!({ "text": text, "priority": priority }))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    }
+
+    let mut res = TestClient::get(url("/todos"))
+        .query("sort_by", "priority")
+        .query("order", "desc")
+        .send(&service)
+        .await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    let texts: Vec<&str> = list.iter().map(|todo| todo["text"].as_str().unwrap()).collect();
+    assert_eq!(texts, ["high", "low", "none"]);
+
+    let mut res = TestClient::get(url("/todos"))
+        .query("min_priority", 2)
+        .send(&service)
+        .await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0]["text"], "high");
+
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "too high", "priority": 9 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn tags_are_normalized_and_filterable() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "report", "tags": [" Work ", "urgent", "work"] }))
+        .send(&service)
+        .await;
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["tags"], json!(["urgent", "work"]));
+    TestClient::post(url("/todos"))
+        .json(&json!({ "text": "groceries", "tags": ["home"] }))
+        .send(&service)
+        .await;
+
+    let mut res = TestClient::get(url("/todos")).query("tag", "WORK").send(&service).await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0]["text"], "report");
+
+    let mut res = TestClient::get(url("/todos/tags")).send(&service).await;
+    let tags: Value = res.take_json().await.unwrap();
+    assert_eq!(tags, json!(["home", "urgent", "work"]));
+}
+
+#[tokio::test]
+async fn list_streams_ndjson_when_requested() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "first").await;
+    create(&service, "second").await;
+
+    let mut res = TestClient::get(url("/todos"))
+        .add_header("accept", "application/x-ndjson", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+    let body = res.take_string().await.unwrap();
+    let lines: Vec<Value> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["text"], "first");
+    assert_eq!(lines[1]["text"], "second");
+}
+
+#[tokio::test]
+async fn list_exports_filtered_csv() {
+    let (_guard, service) = setup().await;
+
+    let first = create(&service, "eggs, milk").await;
+    create(&service, "keep \"quoted\"").await;
+    create(&service, "skip this").await;
+    TestClient::post(url(&format!("/todos/{}/toggle", first["id"])))
+        .send(&service)
+        .await;
+
+    let mut res = TestClient::get(url("/todos"))
+        .query("format", "csv")
+        .query("q", "e")
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    assert_eq!(res.headers()["content-type"], "text/csv; charset=utf-8");
+    assert!(res.headers()["content-disposition"].to_str().unwrap().starts_with("attachment"));
+    let body = res.take_string().await.unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines[0], "id,text,completed");
+    assert_eq!(lines[1], format!("{},\"eggs, milk\",true", first["id"]));
+    assert_eq!(lines.len(), 3);
+    assert!(lines[2].ends_with(",\"keep \"\"quoted\"\"\",false"));
+}
+
+#[tokio::test]
+async fn overdue_filter_returns_pending_todos_past_due() {
+    let (_guard, service) = setup().await;
+
+    let cases = [
+        ("late", false, Some("2000-01-01T00:00:00Z")),
+        ("late but done", true, Some("2000-01-01T00:00:00Z")),
+        ("not yet", false, Some("2999-01-01T00:00:00Z")),
+        ("no date", false, None),
+    ];
+    for (text, completed, due_date) in cases {
+        let res = TestClient::post(url("/todos"))
+            .json(&json!({ "text": text, "completed": completed, "due_date": due_date }))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    }
+
+    let mut res = TestClient::get(url("/todos"))
+        .query("overdue", true)
+        .send(&service)
+        .await;
+    let todos: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0]["text"], "late");
+
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "bad date", "due_date": "tomorrow" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn oversized_body_returns_payload_too_large() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "x".repeat(20 * 1024) }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::PAYLOAD_TOO_LARGE));
+    let error: Value = res.take_json().await.unwrap();
+    assert_eq!(error["code"], 413);
+}
+
+#[tokio::test]
+async fn bulk_routes_accept_larger_bodies() {
+    let (_guard, service) = setup().await;
+
+    // unos 30 KiB: mas que MAX_BODY_SIZE pero menos que MAX_BULK_BODY_SIZE
+    let batch: Vec<Value> = (0..60)
+        .map(|i| json!({ "id": 5000 + i, "text": "x".repeat(500), "completed": false }))
+        .collect();
+    let res = TestClient::post(url("/todos/batch")).json(&batch).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "x".repeat(500), "tags": vec!["y".repeat(100); 300] }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::PAYLOAD_TOO_LARGE));
+}
+
+#[tokio::test]
+async fn declared_content_length_is_checked_before_reading() {
+    let (_guard, service) = setup().await;
+
+    // el cuerpo real es chico, pero el Content-Length anuncia 10 MiB
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "small" }))
+        .add_header("content-length", 10 * 1024 * 1024, true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::PAYLOAD_TOO_LARGE));
+    let error: Value = res.take_json().await.unwrap();
+    // el chequeo temprano usa el mayor de los limites, el de los endpoints bulk
+    assert_eq!(error["message"], "request body must be at most 1048576 bytes");
+}
+
+#[tokio::test]
+async fn etag_supports_conditional_requests() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "cached").await;
+    let path = format!("/todos/{}", todo["id"]);
+
+    let res = TestClient::get(url(&path)).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let etag = res.headers()["etag"].to_str().unwrap().to_string();
+    // el ETag es "id-version"
+    assert_eq!(etag, format!("\"{}-1\"", todo["id"]));
+
+    let res = TestClient::get(url(&path))
+        .add_header("if-none-match", &etag, true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_MODIFIED));
+
+    let res = TestClient::patch(url(&path))
+        .add_header("if-match", &etag, true)
+        .json(&json!({ "completed": true, "version": 1 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    assert_eq!(res.headers()["etag"].to_str().unwrap(), format!("\"{}-2\"", todo["id"]));
+
+    // el ETag viejo ya no coincide, asi que ni el PATCH ni el DELETE se aplican
+    let res = TestClient::patch(url(&path))
+        .add_header("if-match", &etag, true)
+        .json(&json!({ "completed": false, "version": 2 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::PRECONDITION_FAILED));
+    let res = TestClient::delete(url(&path))
+        .add_header("if-match", &etag, true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::PRECONDITION_FAILED));
+}
+
+#[tokio::test]
+async fn list_rejects_bad_offset_and_clamps_limit() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "only").await;
+
+    let res = TestClient::get(url("/todos"))
+        .query("offset", -1)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+
+    let mut res = TestClient::get(url("/todos"))
+        .query("limit", 1_000_000)
+        .query("paged", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    assert_eq!(res.headers()["x-limit-clamped"], "100");
+    let page: Value = res.take_json().await.unwrap();
+    assert_eq!(page["limit"], 100);
+    assert_eq!(page["total"], 1);
+
+    // sin limit se usa DEFAULT_LIMIT (50), que no es un recorte
+    let mut res = TestClient::get(url("/todos")).query("paged", true).send(&service).await;
+    assert_eq!(res.headers()["x-limit"], "50");
+    assert!(!res.headers().contains_key("x-limit-clamped"));
+    let page: Value = res.take_json().await.unwrap();
+    assert_eq!(page["limit"], 50);
+}
+
+#[tokio::test]
+async fn move_reorders_todos_by_position() {
+    let (_guard, service) = setup().await;
+
+    let first = create(&service, "first").await;
+    let second = create(&service, "second").await;
+    let third = create(&service, "third").await;
+
+    let res = TestClient::post(url(&format!("/todos/{}/move", first["id"])))
+        .json(&json!({ "position": 1 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    // el que corrio de lugar cambia de version (y de ETag); el que quedo donde estaba no
+    for (todo, version) in [(&second, 2), (&third, 1)] {
+        let res = TestClient::get(url(&format!("/todos/{}", todo["id"]))).send(&service).await;
+        assert_eq!(res.headers()["etag"], format!("\"{}-{version}\"", todo["id"]));
+    }
+
+    let texts = |todos: Vec<Value>| -> Vec<String> {
+        todos.iter().map(|todo| todo["text"].as_str().unwrap().to_string()).collect()
+    };
+    let mut res = TestClient::get(url("/todos"))
+        .query("sort_by", "position")
+        .send(&service)
+        .await;
+    assert_eq!(texts(res.take_json().await.unwrap()), ["second", "first", "third"]);
+
+    // una posicion fuera de rango lo deja al final
+    TestClient::post(url(&format!("/todos/{}/move", first["id"])))
+        .json(&json!({ "position": 99 }))
+        .send(&service)
+        .await;
+    let mut res = TestClient::get(url("/todos"))
+        .query("sort_by", "position")
+        .send(&service)
+        .await;
+    assert_eq!(texts(res.take_json().await.unwrap()), ["second", "third", "first"]);
+}
+
+#[tokio::test]
+async fn reorder_places_a_todo_before_or_after_another() {
+    let (_guard, service) = setup().await;
+
+    let first = create(&service, "first").await;
+    let second = create(&service, "second").await;
+    let third = create(&service, "third").await;
+
+    let texts = |todos: Vec<Value>| -> Vec<String> {
+        todos.iter().map(|todo| todo["text"].as_str().unwrap().to_string()).collect()
+    };
+    let reorder = |id: &Value, body: Value| {
+        TestClient::post(url(&format!("/todos/{id}/reorder"))).json(&body).send(&service)
+    };
+
+    reorder(&third["id"], json!({ "before": first["id"] })).await;
+    let mut res = TestClient::get(url("/todos")).query("sort_by", "position").send(&service).await;
+    assert_eq!(texts(res.take_json().await.unwrap()), ["third", "first", "second"]);
+
+    reorder(&third["id"], json!({ "after": second["id"] })).await;
+    let mut res = TestClient::get(url("/todos")).query("sort_by", "position").send(&service).await;
+    assert_eq!(texts(res.take_json().await.unwrap()), ["first", "second", "third"]);
+
+    let res = reorder(&third["id"], json!({ "after": third["id"] })).await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    let res = reorder(&third["id"], json!({ "before": 9999 })).await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    let res = reorder(&third["id"], json!({ "before": first["id"], "after": second["id"] })).await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn soft_deleted_todos_can_be_restored() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "oops").await;
+    let path = format!("/todos/{}", todo["id"]);
+
+    let res = TestClient::delete(url(&path)).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+    let res = TestClient::get(url(&path)).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    let todos: Vec<Value> = res.take_json().await.unwrap();
+    assert!(todos.is_empty());
+    let mut res = TestClient::get(url("/todos"))
+        .query("include_deleted", true)
+        .send(&service)
+        .await;
+    let todos: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(todos.len(), 1);
+    assert!(todos[0]["deleted_at"].is_string());
+
+    let mut res = TestClient::post(url(&format!("{path}/restore"))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let restored: Value = res.take_json().await.unwrap();
+    assert!(restored["deleted_at"].is_null());
+
+    let res = TestClient::delete(url(&path)).query("hard", true).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+    let res = TestClient::post(url(&format!("{path}/restore"))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn clear_counts_only_live_todos_but_purges_tombstones() {
+    let (_guard, service) = setup().await;
+
+    let live = create(&service, "live").await;
+    let gone = create(&service, "gone").await;
+    let path = format!("/todos/{}", gone["id"]);
+    TestClient::delete(url(&path)).send(&service).await;
+
+    let mut events = todo_api::events::subscribe();
+    let mut res = TestClient::delete(url("/todos")).query("confirm", true).send(&service).await;
+    assert_eq!(res.take_json::<Value>().await.unwrap(), json!({ "deleted": 1 }));
+    let deleted = serde_json::to_value(events.recv().await.unwrap()).unwrap();
+    assert_eq!((deleted["type"].clone(), deleted["todo"]["id"].clone()), (json!("deleted"), live["id"].clone()));
+    // el siguiente evento ya es el del create: el borrado logico no se volvio a avisar
+    let next = create(&service, "next").await;
+    assert_eq!(serde_json::to_value(events.recv().await.unwrap()).unwrap()["todo"]["id"], next["id"]);
+
+    let res = TestClient::post(url(&format!("{path}/restore"))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn idempotency_key_replays_create() {
+    let (_guard, service) = setup().await;
+
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let mut res = TestClient::post(url("/todos"))
+            .add_header("idempotency-key", "retry-me", true)
+            .json(&json!({ "text": "only once" }))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::CREATED));
+        let todo: Value = res.take_json().await.unwrap();
+        ids.push(todo["id"].clone());
+    }
+    assert_eq!(ids[0], ids[1]);
+
+    let mut res = TestClient::get(url("/todos/count")).send(&service).await;
+    let counts: Value = res.take_json().await.unwrap();
+    assert_eq!(counts["total"], 1);
+}
+
+#[tokio::test]
+async fn mutations_publish_events() {
+    let (_guard, service) = setup().await;
+
+    let mut events = todo_api::events::subscribe();
+    let todo = create(&service, "watched").await;
+    TestClient::delete(url(&format!("/todos/{}", todo["id"])))
+        .send(&service)
+        .await;
+
+    let created = serde_json::to_value(events.recv().await.unwrap()).unwrap();
+    assert_eq!(created["type"], "created");
+    assert_eq!(created["todo"]["id"], todo["id"]);
+    let deleted = serde_json::to_value(events.recv().await.unwrap()).unwrap();
+    assert_eq!(deleted["type"], "deleted");
+}
+
+#[tokio::test]
+async fn stream_sends_sse_headers() {
+    let (_guard, service) = setup().await;
+
+    // el stream no termina solo, asi que solo miramos los headers de la respuesta
+    let res = TestClient::get(url("/todos/stream"))
+        .add_header("accept-encoding", "gzip", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code.unwrap_or(StatusCode::OK), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/event-stream");
+    // el SSE no se comprime, los eventos tienen que salir apenas ocurren
+    assert!(res.headers().get("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn batch_patch_completes_listed_todos() {
+    let (_guard, service) = setup().await;
+
+    let first = create(&service, "first").await;
+    let second = create(&service, "second").await;
+
+    let mut res = TestClient::patch(url("/todos/batch"))
+        .json(&json!({ "ids": [first["id"], second["id"], 9999], "completed": true }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code.unwrap_or(StatusCode::OK), StatusCode::OK);
+    let result: Value = res.take_json().await.unwrap();
+    assert_eq!(result, json!({ "updated": [first["id"], second["id"]], "unchanged": [], "missing": [9999] }));
+
+    let mut res = TestClient::get(url("/todos/count")).send(&service).await;
+    let counts: Value = res.take_json().await.unwrap();
+    assert_eq!(counts["completed"], 2);
+
+    // los que ya estan completos no cambian de version
+    let third = create(&service, "third").await;
+    let mut res = TestClient::patch(url("/todos/batch"))
+        .json(&json!({ "ids": [first["id"], third["id"]], "completed": true }))
+        .send(&service)
+        .await;
+    let result: Value = res.take_json().await.unwrap();
+    assert_eq!(result, json!({ "updated": [third["id"]], "unchanged": [first["id"]], "missing": [] }));
+    let mut res = TestClient::get(url(&format!("/todos/{}", first["id"]))).send(&service).await;
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["version"], first["version"].as_u64().unwrap() + 1);
+
+    let res = TestClient::patch(url("/todos/batch"))
+        .json(&json!({ "ids": [], "completed": true }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn non_json_body_is_unsupported_media_type() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .raw_form("text=buy+milk")
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+    let error: Value = res.take_json().await.unwrap();
+    assert_eq!(error["code"], 415);
+}
+
+#[tokio::test]
+async fn metrics_are_exposed_in_prometheus_format() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "measured").await;
+    TestClient::get(url("/todos/9999")).send(&service).await;
+
+    let mut res = TestClient::get(url("/metrics")).send(&service).await;
+    assert_eq!(res.status_code.unwrap_or(StatusCode::OK), StatusCode::OK);
+    let body = res.take_string().await.unwrap();
+    assert!(body.contains("# TYPE todo_api_requests_total counter"));
+    assert!(body.contains("todo_api_requests_by_method_total{method=\"POST\"}"));
+    assert!(body.contains("todo_api_todos 1\n"));
+    // los contadores son globales a todos los tests, asi que solo miramos que haya al menos un error
+    let errors = body
+        .lines()
+        .find_map(|line| line.strip_prefix("todo_api_error_responses_total "))
+        .unwrap();
+    assert!(errors.parse::<u64>().unwrap() >= 1);
+}
+
+#[tokio::test]
+async fn dedupe_rejects_pending_todo_with_same_text() {
+    let (_guard, service) = setup().await;
+
+    let existing = create(&service, "Buy milk").await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .query("dedupe", true)
+        .json(&json!({ "text": "  buy MILK " }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+    let error: Value = res.take_json().await.unwrap();
+    assert_eq!(error["ids"], json!([existing["id"]]));
+
+    // sin el flag se crea igual
+    create(&service, "buy milk").await;
+}
+
+#[tokio::test]
+async fn subtasks_validate_parent_and_reparent_on_delete() {
+    let (_guard, service) = setup().await;
+
+    let root = create(&service, "root").await;
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "child", "parent_id": root["id"] }))
+        .send(&service)
+        .await;
+    let child: Value = res.take_json().await.unwrap();
+
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "orphan", "parent_id": 9999 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+
+    // root no puede pasar a ser hijo de su propio hijo
+    let res = TestClient::patch(url(&format!("/todos/{}", root["id"])))
+        .json(&json!({ "parent_id": child["id"], "version": 1 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+
+    let mut res = TestClient::get(url(&format!("/todos/{}/children", root["id"])))
+        .send(&service)
+        .await;
+    let children: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0]["id"], child["id"]);
+
+    let mut events = todo_api::events::subscribe();
+    TestClient::delete(url(&format!("/todos/{}", root["id"])))
+        .send(&service)
+        .await;
+    let mut res = TestClient::get(url(&format!("/todos/{}", child["id"]))).send(&service).await;
+    let reparented: Value = res.take_json().await.unwrap();
+    assert!(reparented["parent_id"].is_null());
+    // cambiar de padre es un cambio como cualquier otro: version nueva y evento updated
+    assert_eq!(reparented["version"], child["version"].as_u64().unwrap() + 1);
+    let deleted = serde_json::to_value(events.recv().await.unwrap()).unwrap();
+    assert_eq!(deleted["type"], "deleted");
+    let updated = serde_json::to_value(events.recv().await.unwrap()).unwrap();
+    assert_eq!(updated["type"], "updated");
+    assert_eq!(updated["todo"], reparented);
+}
+
+#[tokio::test]
+async fn archive_moves_completed_todos_out_of_the_list() {
+    let (_guard, service) = setup().await;
+
+    let done = create(&service, "done").await;
+    create(&service, "active").await;
+    TestClient::post(url(&format!("/todos/{}/toggle", done["id"])))
+        .send(&service)
+        .await;
+
+    let mut res = TestClient::post(url("/todos/archive")).send(&service).await;
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body, json!({ "archived": 1 }));
+
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    let todos: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0]["text"], "active");
+    let mut res = TestClient::get(url("/todos/archived")).send(&service).await;
+    let archived: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(archived[0]["id"], done["id"]);
+
+    TestClient::post(url(&format!("/todos/{}/unarchive", done["id"])))
+        .send(&service)
+        .await;
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    let todos: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(todos.len(), 2);
+}
+
+#[tokio::test]
+async fn unsupported_method_returns_method_not_allowed() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::patch(url("/todos")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::METHOD_NOT_ALLOWED));
+    assert_eq!(res.headers()["allow"], "GET, POST, DELETE");
+    let error: Value = res.take_json().await.unwrap();
+    assert_eq!(error["code"], 405);
+
+    let mut res = TestClient::get(url("/nope")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+    let error: Value = res.take_json().await.unwrap();
+    assert_eq!(error, json!({ "code": 404, "message": "not found", "path": "/nope" }));
+}
+
+#[tokio::test]
+async fn merge_patch_null_clears_optional_fields() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "prioritized", "priority": 3 }))
+        .send(&service)
+        .await;
+    let todo: Value = res.take_json().await.unwrap();
+    let path = format!("/todos/{}", todo["id"]);
+
+    // con JSON comun un null es lo mismo que no mandar el campo
+    let mut res = TestClient::patch(url(&path))
+        .json(&json!({ "priority": null, "version": 1 }))
+        .send(&service)
+        .await;
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["priority"], 3);
+
+    let mut res = TestClient::patch(url(&path))
+        .raw_json(r#"{"priority": null, "text": "merged", "version": 2}"#)
+        .add_header("content-type", "application/merge-patch+json", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code.unwrap_or(StatusCode::OK), StatusCode::OK);
+    let todo: Value = res.take_json().await.unwrap();
+    assert!(todo["priority"].is_null());
+    assert_eq!(todo["text"], "merged");
+
+    let res = TestClient::patch(url(&path))
+        .raw_json("[1, 2]")
+        .add_header("content-type", "application/merge-patch+json", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn stale_version_is_rejected_with_the_current_todo() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "versioned").await;
+    assert_eq!(todo["version"], 1);
+    let path = format!("/todos/{}", todo["id"]);
+
+    let mut res = TestClient::patch(url(&path))
+        .json(&json!({ "text": "first writer", "version": 1 }))
+        .send(&service)
+        .await;
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["version"], 2);
+
+    // el segundo cliente todavia tiene la version 1: recibe el todo actual para re-aplicar su cambio
+    let mut res = TestClient::put(url(&path))
+        .json(&json!({ "id": todo["id"], "text": "second writer", "completed": false, "version": 1 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+    let current: Value = res.take_json().await.unwrap();
+    assert_eq!(current["text"], "first writer");
+    assert_eq!(current["version"], 2);
+
+    let res = TestClient::patch(url(&path))
+        .json(&json!({ "text": "no version" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn duplicate_copies_todo_as_pending() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "weekly report", "completed": true, "tags": ["work"] }))
+        .send(&service)
+        .await;
+    let source: Value = res.take_json().await.unwrap();
+
+    let mut res = TestClient::post(url(&format!("/todos/{}/duplicate", source["id"])))
+        .query("suffix", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    let copy: Value = res.take_json().await.unwrap();
+    assert_ne!(copy["id"], source["id"]);
+    assert_eq!(copy["text"], "weekly report (copy)");
+    assert_eq!(copy["completed"], false);
+    assert_eq!(copy["tags"], json!(["work"]));
+    assert_eq!(res.headers()["location"], format!("/todos/{}", copy["id"]));
+
+    let res = TestClient::post(url("/todos/9999/duplicate")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn list_filters_by_id_range() {
+    let (_guard, service) = setup().await;
+
+    let mut ids = Vec::new();
+    for text in ["one", "two", "three", "four"] {
+        ids.push(create(&service, text).await["id"].as_i64().unwrap());
+    }
+
+    let mut res = TestClient::get(url("/todos"))
+        .query("min_id", ids[1])
+        .query("max_id", ids[2])
+        .send(&service)
+        .await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    let texts: Vec<&str> = list.iter().map(|todo| todo["text"].as_str().unwrap()).collect();
+    assert_eq!(texts, ["two", "three"]);
+
+    let res = TestClient::get(url("/todos"))
+        .query("min_id", ids[2])
+        .query("max_id", ids[1])
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn list_sends_link_headers() {
+    let (_guard, service) = setup().await;
+
+    for text in ["one", "two", "three", "four", "five"] {
+        create(&service, text).await;
+    }
+
+    let res = TestClient::get(url("/todos"))
+        .query("sort_by", "text")
+        .query("offset", 2)
+        .query("limit", 2)
+        .send(&service)
+        .await;
+    assert_eq!(
+        res.headers()["link"],
+        "</todos?sort_by=text&offset=0&limit=2>; rel=\"first\", \
+         </todos?sort_by=text&offset=0&limit=2>; rel=\"prev\", \
+         </todos?sort_by=text&offset=4&limit=2>; rel=\"next\", \
+         </todos?sort_by=text&offset=4&limit=2>; rel=\"last\""
+    );
+
+    // en la primera y la ultima pagina no van prev ni next
+    let res = TestClient::get(url("/todos")).query("limit", 2).send(&service).await;
+    let links = res.headers()["link"].to_str().unwrap();
+    assert!(links.contains("rel=\"next\""));
+    assert!(!links.contains("rel=\"prev\""));
+
+    let res = TestClient::get(url("/todos"))
+        .query("offset", 4)
+        .query("limit", 2)
+        .send(&service)
+        .await;
+    let links = res.headers()["link"].to_str().unwrap();
+    assert!(links.contains("rel=\"prev\""));
+    assert!(!links.contains("rel=\"next\""));
+}
+
+#[tokio::test]
+async fn list_honors_if_modified_since() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "poll me").await;
+    let newer = create(&service, "delete me").await;
+
+    // en el mismo segundo del cambio todavia no hay Last-Modified
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+    let res = TestClient::get(url("/todos")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let last_modified = res.headers()["last-modified"].to_str().unwrap().to_string();
+
+    let mut res = TestClient::get(url("/todos"))
+        .add_header("if-modified-since", &last_modified, true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_MODIFIED));
+    assert_eq!(res.headers()["last-modified"], last_modified.as_str());
+    assert!(res.take_string().await.unwrap().is_empty());
+
+    // una fecha vieja o que no se entiende devuelve la lista completa
+    for since in ["Mon, 01 Jan 2001 00:00:00 GMT", "yesterday"] {
+        let res = TestClient::get(url("/todos"))
+            .add_header("if-modified-since", since, true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+    }
+
+    // borrar el todo mas nuevo tambien es un cambio, aunque ya no este en la lista
+    TestClient::delete(url(&format!("/todos/{}", newer["id"]))).send(&service).await;
+    let res = TestClient::get(url("/todos"))
+        .add_header("if-modified-since", &last_modified, true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+}
+
+#[tokio::test]
+async fn cursor_pages_are_stable_under_changes() {
+    let (_guard, service) = setup().await;
+
+    let mut ids = Vec::new();
+    for text in ["one", "two", "three", "four", "five"] {
+        ids.push(create(&service, text).await["id"].clone());
+    }
+
+    let mut res = TestClient::get(url("/todos")).query("limit", 2).send(&service).await;
+    let cursor = res.headers()["x-next-cursor"].to_str().unwrap().to_string();
+    let page: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(page.iter().map(|todo| todo["id"].clone()).collect::<Vec<_>>(), ids[..2]);
+
+    // borrar uno de la primera pagina no corre la siguiente, como pasaria con offset
+    TestClient::delete(url(&format!("/todos/{}", ids[0]))).send(&service).await;
+    let mut res = TestClient::get(url("/todos"))
+        .query("limit", 2)
+        .query("cursor", &cursor)
+        .query("paged", true)
+        .send(&service)
+        .await;
+    let page: Value = res.take_json().await.unwrap();
+    let page_ids: Vec<Value> = page["items"].as_array().unwrap().iter().map(|todo| todo["id"].clone()).collect();
+    assert_eq!(page_ids, ids[2..4]);
+    let cursor = page["next_cursor"].as_str().unwrap().to_string();
+
+    let res = TestClient::get(url("/todos")).query("limit", 2).query("cursor", &cursor).send(&service).await;
+    assert!(!res.headers().contains_key("x-next-cursor"));
+
+    for (name, value) in [("offset", "1"), ("sort_by", "text")] {
+        let res = TestClient::get(url("/todos"))
+            .query("cursor", &cursor)
+            .query(name, value)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+    let res = TestClient::get(url("/todos")).query("cursor", "not-a-cursor").send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn completed_at_follows_completion() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "finish report").await;
+    assert!(todo["completed_at"].is_null());
+    let id = todo["id"].as_i64().unwrap();
+
+    let mut res = TestClient::post(url(&format!("/todos/{id}/toggle"))).send(&service).await;
+    let done: Value = res.take_json().await.unwrap();
+    assert!(done["completed_at"].is_string());
+
+    // si completed no cambia, completed_at queda como estaba
+    let mut res = TestClient::patch(url(&format!("/todos/{id}")))
+        .json(&json!({ "version": done["version"], "text": "finish the report", "completed": true }))
+        .send(&service)
+        .await;
+    let patched: Value = res.take_json().await.unwrap();
+    assert_eq!(patched["completed_at"], done["completed_at"]);
+
+    let mut res = TestClient::patch(url(&format!("/todos/{id}")))
+        .json(&json!({ "version": patched["version"], "completed": false }))
+        .send(&service)
+        .await;
+    let pending: Value = res.take_json().await.unwrap();
+    assert!(pending["completed_at"].is_null());
+}
+
+#[tokio::test]
+async fn delete_can_return_the_deleted_todo() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "undo me").await;
+    let path = format!("/todos/{}", todo["id"]);
+
+    let mut res = TestClient::delete(url(&path)).query("return", true).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let deleted: Value = res.take_json().await.unwrap();
+    assert_eq!(deleted["id"], todo["id"]);
+    assert_eq!(deleted["text"], "undo me");
+    assert!(deleted["deleted_at"].is_string());
+
+    let res = TestClient::post(url(&format!("{path}/restore"))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let res = TestClient::delete(url(&path)).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+}
+
+#[tokio::test]
+async fn dry_run_does_not_change_the_store() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "keep me").await;
+    let id = todo["id"].as_i64().unwrap();
+
+    let mut res = TestClient::post(url("/todos"))
+        .query("dry_run", true)
+        .json(&json!({ "text": "  just checking " }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let preview: Value = res.take_json().await.unwrap();
+    assert_eq!(preview["text"], "just checking");
+
+    // los errores salen igual que sin dry_run
+    let res = TestClient::post(url("/todos"))
+        .query("dry_run", true)
+        .json(&json!({ "text": " " }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+
+    let mut res = TestClient::patch(url(&format!("/todos/{id}")))
+        .query("dry_run", true)
+        .json(&json!({ "version": todo["version"], "text": "changed" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let preview: Value = res.take_json().await.unwrap();
+    assert_eq!(preview["text"], "changed");
+    assert_eq!(preview["version"], todo["version"].as_u64().unwrap() + 1);
+
+    let res = TestClient::patch(url(&format!("/todos/{id}")))
+        .query("dry_run", true)
+        .json(&json!({ "version": 99, "text": "changed" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+
+    let mut res = TestClient::delete(url(&format!("/todos/{id}")))
+        .query("dry_run", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let removed: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(removed.len(), 1);
+
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(list, [todo]);
+}
+
+#[tokio::test]
+async fn creates_stop_at_max_todos() {
+    let (_guard, service) = setup().await;
+
+    std::env::set_var("MAX_TODOS", "2");
+    create(&service, "one").await;
+    create(&service, "two").await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "three" }))
+        .send(&service)
+        .await;
+    let status = res.status_code;
+    let body: Value = res.take_json().await.unwrap();
+
+    let batch = TestClient::post(url("/todos/batch"))
+        .json(&json!([{ "id": 100, "text": "batched", "completed": false }]))
+        .send(&service)
+        .await;
+    std::env::remove_var("MAX_TODOS");
+
+    assert_eq!(status, Some(StatusCode::INSUFFICIENT_STORAGE));
+    assert_eq!(body["code"], 507);
+    assert_eq!(batch.status_code, Some(StatusCode::INSUFFICIENT_STORAGE));
+    create(&service, "three").await;
+}
+
+#[tokio::test]
+async fn json_patch_applies_all_operations_or_none() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "patch me", "priority": 3 }))
+        .send(&service)
+        .await;
+    let todo: Value = res.take_json().await.unwrap();
+    let path = format!("/todos/{}", todo["id"]);
+
+    let mut res = TestClient::patch(url(&path))
+        .raw_json(
+            r#"[
+                {"op": "test", "path": "/version", "value": 1},
+                {"op": "replace", "path": "/text", "value": "patched"},
+                {"op": "remove", "path": "/priority"},
+                {"op": "add", "path": "/tags/-", "value": "work"}
+            ]"#,
+        )
+        .add_header("content-type", "application/json-patch+json", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code.unwrap_or(StatusCode::OK), StatusCode::OK);
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["text"], "patched");
+    assert!(todo["priority"].is_null());
+    assert_eq!(todo["tags"], json!(["work"]));
+
+    // un test que no da rechaza el patch entero
+    let res = TestClient::patch(url(&path))
+        .raw_json(
+            r#"[
+                {"op": "test", "path": "/version", "value": 2},
+                {"op": "replace", "path": "/text", "value": "lost"},
+                {"op": "test", "path": "/completed", "value": true}
+            ]"#,
+        )
+        .add_header("content-type", "application/json-patch+json", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+
+    let res = TestClient::patch(url(&path))
+        .raw_json(r#"[{"op": "test", "path": "/version", "value": 2}, {"op": "remove", "path": "/missing"}]"#)
+        .add_header("content-type", "application/json-patch+json", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::UNPROCESSABLE_ENTITY));
+
+    let mut res = TestClient::get(url(&path)).send(&service).await;
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["text"], "patched");
+}
+
+#[tokio::test]
+async fn list_returns_todos_updated_since() {
+    let (_guard, service) = setup().await;
+
+    let first = create(&service, "first").await;
+    create(&service, "second").await;
+    TestClient::patch(url(&format!("/todos/{}", first["id"])))
+        .json(&json!({ "version": first["version"], "text": "first, changed" }))
+        .send(&service)
+        .await;
+
+    let mut res = TestClient::get(url("/todos"))
+        .query("updated_since", first["updated_at"].as_str().unwrap())
+        .send(&service)
+        .await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    let texts: Vec<&str> = list.iter().map(|todo| todo["text"].as_str().unwrap()).collect();
+    assert_eq!(texts, ["second", "first, changed"]);
+
+    let res = TestClient::get(url("/todos"))
+        .query("updated_since", "yesterday")
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn multi_tenant_mode_scopes_todos_by_user() {
+    let (_guard, service) = setup().await;
+
+    std::env::set_var("MULTI_TENANT", "true");
+    for user in ["alice", "bob"] {
+        TestClient::delete(url("/todos"))
+            .query("confirm", true)
+            .add_header("x-user-id", user, true)
+            .send(&service)
+            .await;
+    }
+
+    let mut res = TestClient::post(url("/todos"))
+        .add_header("x-user-id", "alice", true)
+        .json(&json!({ "text": "alice's todo" }))
+        .send(&service)
+        .await;
+    let created: Value = res.take_json().await.unwrap();
+    let path = format!("/todos/{}", created["id"]);
+
+    let mut res = TestClient::get(url("/todos")).add_header("x-user-id", "bob", true).send(&service).await;
+    let bobs: Vec<Value> = res.take_json().await.unwrap();
+    let bob_get = TestClient::get(url(&path)).add_header("x-user-id", "bob", true).send(&service).await;
+    let alice_get = TestClient::get(url(&path)).add_header("x-user-id", "alice", true).send(&service).await;
+    let missing = TestClient::get(url("/todos")).send(&service).await;
+    let invalid = TestClient::get(url("/todos")).add_header("x-user-id", "../etc", true).send(&service).await;
+    // un usuario que nunca escribio ve una lista vacia sin que se le cree un archivo
+    let ghost = format!("ghost-{}", std::process::id());
+    let mut res = TestClient::get(url("/todos")).add_header("x-user-id", &ghost, true).send(&service).await;
+    let ghosts: Vec<Value> = res.take_json().await.unwrap();
+    let ghost_file = std::env::temp_dir().join(format!("todo_api_test_{}.{ghost}.json", std::process::id()));
+    std::env::remove_var("MULTI_TENANT");
+
+    assert!(ghosts.is_empty());
+    assert!(!ghost_file.exists());
+
+    assert!(bobs.is_empty());
+    assert_eq!(bob_get.status_code, Some(StatusCode::NOT_FOUND));
+    assert_eq!(alice_get.status_code.unwrap_or(StatusCode::OK), StatusCode::OK);
+    assert_eq!(missing.status_code, Some(StatusCode::BAD_REQUEST));
+    assert_eq!(invalid.status_code, Some(StatusCode::BAD_REQUEST));
+
+    // sin el modo multi-tenant se vuelve al store de siempre
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    assert!(list.is_empty());
+}
+
+#[tokio::test]
+async fn corrupt_tenant_file_is_a_server_error() {
+    let (_guard, service) = setup().await;
+
+    let user = format!("broken-{}", std::process::id());
+    let file = std::env::temp_dir().join(format!("todo_api_test_{}.{user}.json", std::process::id()));
+    std::fs::write(&file, "not json").unwrap();
+    std::env::set_var("MULTI_TENANT", "true");
+    let mut broken = TestClient::get(url("/todos")).add_header("x-user-id", &user, true).send(&service).await;
+    let others = TestClient::get(url("/todos")).add_header("x-user-id", "alice", true).send(&service).await;
+    std::env::remove_var("MULTI_TENANT");
+    std::fs::remove_file(&file).unwrap();
+
+    assert_eq!(broken.status_code, Some(StatusCode::INTERNAL_SERVER_ERROR));
+    let error: Value = broken.take_json().await.unwrap();
+    assert_eq!(error["code"], 500);
+    assert_eq!(others.status_code.unwrap_or(StatusCode::OK), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn bulk_delete_reports_deleted_and_missing_ids() {
+    let (_guard, service) = setup().await;
+
+    let keep = create(&service, "keep").await;
+    let first = create(&service, "first").await;
+    let second = create(&service, "second").await;
+
+    let mut res = TestClient::post(url("/todos/delete"))
+        .json(&json!({ "ids": [first["id"], second["id"], 9999] }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code.unwrap_or(StatusCode::OK), StatusCode::OK);
+    let result: Value = res.take_json().await.unwrap();
+    assert_eq!(result, json!({ "deleted": [first["id"], second["id"]], "missing": [9999] }));
+
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(list, [keep]);
+
+    let res = TestClient::post(url("/todos/delete"))
+        .json(&json!({ "ids": [] }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn search_ranks_prefix_matches_first() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "call the plumber").await;
+    create(&service, "plan the trip").await;
+    create(&service, "buy a new planter").await;
+    create(&service, "water the plants").await;
+
+    let mut res = TestClient::get(url("/todos/search")).query("q", "plan").send(&service).await;
+    let results: Vec<Value> = res.take_json().await.unwrap();
+    let texts: Vec<&str> = results.iter().map(|result| result["todo"]["text"].as_str().unwrap()).collect();
+    assert_eq!(texts, ["plan the trip", "buy a new planter", "water the plants"]);
+    assert!(results[0]["score"].as_u64() > results[1]["score"].as_u64());
+
+    let mut res = TestClient::get(url("/todos/search"))
+        .query("q", "plan")
+        .query("limit", 1)
+        .send(&service)
+        .await;
+    let results: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(results.len(), 1);
+
+    let res = TestClient::get(url("/todos/search")).query("q", " ").send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn search_honours_case_sensitive_search() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "Buy Milk").await;
+    create(&service, "buy milk again").await;
+    let service = &service;
+    let search = |q: &'static str| async move {
+        let mut res = TestClient::get(url("/todos/search")).query("q", q).send(service).await;
+        let results: Vec<Value> = res.take_json().await.unwrap();
+        let mut texts: Vec<String> =
+            results.iter().map(|result| result["todo"]["text"].as_str().unwrap().to_string()).collect();
+        texts.sort();
+        texts
+    };
+
+    // por defecto las mayusculas no importan
+    assert_eq!(search("milk").await, ["Buy Milk", "buy milk again"]);
+
+    // CASE_SENSITIVE_SEARCH se lee en cada request, no hace falta armar otro Service
+    std::env::set_var("CASE_SENSITIVE_SEARCH", "true");
+    let milk = search("milk").await;
+    let capital = search("Milk").await;
+    std::env::remove_var("CASE_SENSITIVE_SEARCH");
+    assert_eq!(milk, ["buy milk again"]);
+    assert_eq!(capital, ["Buy Milk"]);
+}
+
+#[tokio::test]
+async fn board_splits_todos_by_completion() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "write tests").await;
+    create(&service, "write docs").await;
+    let done = create(&service, "write code").await;
+    create(&service, "go home").await;
+    let res = TestClient::post(url(&format!("/todos/{}/toggle", done["id"])))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+
+    let mut res = TestClient::get(url("/todos/board"))
+        .query("q", "write")
+        .query("sort_by", "text")
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let board: Value = res.take_json().await.unwrap();
+    let texts = |column: &str| -> Vec<String> {
+        board[column]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|todo| todo["text"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(texts("pending"), ["write docs", "write tests"]);
+    assert_eq!(texts("completed"), ["write code"]);
+
+    let res = TestClient::get(url("/todos/board")).query("sort_by", "color").send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn fields_selects_todo_fields() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "sparse").await;
+
+    let mut res = TestClient::get(url("/todos")).query("fields", "id, text").send(&service).await;
+    let todos: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(todos, [json!({ "id": todo["id"], "text": "sparse" })]);
+
+    let mut res = TestClient::get(url(&format!("/todos/{}", todo["id"])))
+        .query("fields", "completed")
+        .send(&service)
+        .await;
+    assert_eq!(res.take_json::<Value>().await.unwrap(), json!({ "completed": false }));
+
+    // un campo que no existe es un error, no un objeto vacio
+    for path in ["/todos".to_string(), format!("/todos/{}", todo["id"])] {
+        let res = TestClient::get(url(&path)).query("fields", "id,colour").send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+}
+
+#[tokio::test]
+async fn debug_bodies_still_passes_the_body_on() {
+    let (_guard, _) = setup().await;
+
+    std::env::set_var("DEBUG_BODIES", "true");
+    let service = todo_api::service();
+    std::env::remove_var("DEBUG_BODIES");
+
+    let todo = create(&service, "logged").await;
+    assert_eq!(todo["text"], "logged");
+
+    // un cuerpo que la ruta rechaza no se lee para loguearlo, si no BodySizeLimit ya no lo veria
+    std::env::set_var("DEBUG_BODIES", "true");
+    std::env::set_var("MAX_BODY_SIZE", "64");
+    let service = todo_api::service();
+    std::env::remove_var("DEBUG_BODIES");
+    std::env::remove_var("MAX_BODY_SIZE");
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "x".repeat(100) }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::PAYLOAD_TOO_LARGE));
+}
+
+#[tokio::test]
+async fn next_id_is_after_the_largest_id() {
+    let (_guard, service) = setup().await;
+
+    TestClient::post(url("/todos/batch"))
+        .json(&json!([{ "id": 100000, "text": "imported", "completed": false }]))
+        .send(&service)
+        .await;
+    let mut res = TestClient::get(url("/todos/next-id")).send(&service).await;
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body, json!({ "next_id": 100001 }));
+
+    // el id sirve para crear sin chocar
+    let res = TestClient::post(url("/todos/batch"))
+        .json(&json!([{ "id": 100001, "text": "next", "completed": false }]))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+}
+
+#[tokio::test]
+async fn pretty_indents_json_responses() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "pretty").await;
+
+    let mut res = TestClient::get(url(&format!("/todos/{}", todo["id"])))
+        .query("pretty", true)
+        .send(&service)
+        .await;
+    let body = res.take_string().await.unwrap();
+    assert!(body.starts_with("{\n  \"id\": "));
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), todo);
+
+    // los errores tambien
+    let mut res = TestClient::get(url("/todos/9999")).query("pretty", true).send(&service).await;
+    assert!(res.take_string().await.unwrap().contains("\n  \"code\": 404"));
+    // y los del catcher, para rutas que no existen o metodos que no van
+    for mut res in [
+        TestClient::get(url("/nope")).query("pretty", true).send(&service).await,
+        TestClient::put(url("/todos")).query("pretty", true).send(&service).await,
+    ] {
+        assert!(res.take_string().await.unwrap().starts_with("{\n  \"code\": "));
+    }
+
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    assert!(!res.take_string().await.unwrap().contains('\n'));
+}
+
+#[tokio::test]
+async fn health_reports_uptime_and_todo_count() {
+    let (_guard, service) = setup().await;
+
+    create(&service, "one").await;
+    create(&service, "two").await;
+
+    let mut res = TestClient::get(url("/health")).send(&service).await;
+    let health: Value = res.take_json().await.unwrap();
+    assert_eq!(health["status"], "ok");
+    assert_eq!(health["todo_count"], 2);
+    assert!(health["uptime_secs"].is_u64());
+
+    // un borrado logico deja de contar, igual que en /todos/count
+    let todo = create(&service, "three").await;
+    TestClient::delete(url(&format!("/todos/{}", todo["id"]))).send(&service).await;
+    let mut res = TestClient::get(url("/health")).send(&service).await;
+    let health: Value = res.take_json().await.unwrap();
+    let mut res = TestClient::get(url("/todos/count")).send(&service).await;
+    let counts: Value = res.take_json().await.unwrap();
+    assert_eq!(health["todo_count"], 2);
+    assert_eq!(health["todo_count"], counts["total"]);
+}
+
+#[tokio::test]
+async fn responses_carry_security_headers() {
+    let (_guard, service) = setup().await;
+
+    // tambien en los errores
+    for path in ["/health", "/nope"] {
+        let res = TestClient::get(url(path)).send(&service).await;
+        assert_eq!(res.headers()["x-content-type-options"], "nosniff");
+        assert_eq!(res.headers()["x-frame-options"], "DENY");
+        assert!(!res.headers().contains_key("content-security-policy"));
+    }
+
+    std::env::set_var("CONTENT_SECURITY_POLICY", "default-src 'none'");
+    let service = todo_api::service();
+    std::env::remove_var("CONTENT_SECURITY_POLICY");
+    let res = TestClient::get(url("/health")).send(&service).await;
+    assert_eq!(res.headers()["content-security-policy"], "default-src 'none'");
+
+    std::env::set_var("SECURITY_HEADERS", "false");
+    let service = todo_api::service();
+    std::env::remove_var("SECURITY_HEADERS");
+    let res = TestClient::get(url("/health")).send(&service).await;
+    assert!(!res.headers().contains_key("x-frame-options"));
+}
+
+#[tokio::test]
+async fn completing_a_recurring_todo_creates_the_next_one() {
+    let (_guard, service) = setup().await;
+
+    let due = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "take out the trash", "due_date": due, "recurrence": "Weekly" }))
+        .send(&service)
+        .await;
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["recurrence"], "weekly");
+    let id = todo["id"].as_i64().unwrap();
+
+    let mut res = TestClient::post(url(&format!("/todos/{id}/toggle"))).send(&service).await;
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body["completed"]["completed"], true);
+    assert!(body["completed"]["recurrence"].is_null());
+    let next = &body["next"];
+    assert_eq!(next["text"], "take out the trash");
+    assert_eq!(next["completed"], false);
+    assert_eq!(next["recurrence"], "weekly");
+    let due: chrono::DateTime<chrono::Utc> = serde_json::from_value(todo["due_date"].clone()).unwrap();
+    let next_due: chrono::DateTime<chrono::Utc> = serde_json::from_value(next["due_date"].clone()).unwrap();
+    assert_eq!(next_due - due, chrono::Duration::weeks(1));
+
+    // la recurrencia paso a la nueva: descompletar y completar el viejo no crea otra
+    for _ in 0..2 {
+        let mut res = TestClient::post(url(&format!("/todos/{id}/toggle"))).send(&service).await;
+        let body: Value = res.take_json().await.unwrap();
+        assert_eq!(body["id"], id);
+    }
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    assert_eq!(res.take_json::<Vec<Value>>().await.unwrap().len(), 2);
+
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "sometimes", "recurrence": "hourly" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn export_and_import_round_trip() {
+    let (_guard, service) = setup().await;
+
+    let parent = create(&service, "parent").await;
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "child", "parent_id": parent["id"], "tags": ["x"] }))
+        .send(&service)
+        .await;
+    let child: Value = res.take_json().await.unwrap();
+
+    let mut res = TestClient::get(url("/todos/export")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let export: Value = res.take_json().await.unwrap();
+    assert_eq!(export["format"], 1);
+    assert_eq!(export["todos"], json!([parent, child]));
+
+    // sin merge el store queda igual al documento
+    create(&service, "created after the export").await;
+    let mut res = TestClient::post(url("/todos/import")).json(&export).send(&service).await;
+    let result: Value = res.take_json().await.unwrap();
+    assert_eq!(result, json!({ "imported": [parent["id"], child["id"]], "skipped": [], "conflicted": [] }));
+    let mut res = TestClient::get(url("/todos/export")).send(&service).await;
+    assert_eq!(res.take_json::<Value>().await.unwrap()["todos"], export["todos"]);
+
+    // con merge los ids que ya existen no se tocan; se saltean los invalidos y los que pierden su parent
+    let document = json!({
+        "format": 1,
+        "todos": [
+            { "id": parent["id"], "text": "changed", "completed": false },
+            { "id": 9001, "text": " ", "completed": false },
+            { "id": 9002, "text": "orphan", "completed": false, "parent_id": 9001 },
+            { "id": 9003, "text": "new", "completed": true },
+        ],
+    });
+    let mut res = TestClient::post(url("/todos/import"))
+        .query("merge", true)
+        .json(&document)
+        .send(&service)
+        .await;
+    let result: Value = res.take_json().await.unwrap();
+    assert_eq!(result, json!({ "imported": [9003], "skipped": [9001, 9002], "conflicted": [parent["id"]] }));
+    let mut res = TestClient::get(url(&format!("/todos/{}", parent["id"]))).send(&service).await;
+    assert_eq!(res.take_json::<Value>().await.unwrap()["text"], "parent");
+
+    // un todo importado sin version arranca en la primera y se puede editar con ella
+    let mut res = TestClient::get(url("/todos/9003")).send(&service).await;
+    let imported: Value = res.take_json().await.unwrap();
+    assert_eq!(imported["version"], 1);
+    let res = TestClient::put(url("/todos/9003"))
+        .json(&json!({ "id": 9003, "text": "edited", "completed": false, "version": imported["version"] }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+
+    let res = TestClient::post(url("/todos/import"))
+        .json(&json!({ "format": 2, "todos": [] }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+#[tokio::test]
+async fn large_responses_are_compressed() {
+    let (_guard, service) = setup().await;
+
+    for i in 0..30 {
+        create(&service, &format!("todo number {i}")).await;
+    }
+
+    let mut res = TestClient::get(url("/todos"))
+        .add_header("accept-encoding", "gzip", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    // take_string descomprime segun Content-Encoding, take_json no
+    let list: Vec<Value> = serde_json::from_str(&res.take_string().await.unwrap()).unwrap();
+    assert_eq!(list.len(), 30);
+
+    // el conteo es mas chico que COMPRESSION_MIN_SIZE y sale sin comprimir
+    let res = TestClient::get(url("/todos/count"))
+        .add_header("accept-encoding", "gzip", true)
+        .send(&service)
+        .await;
+    assert!(res.headers().get("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn trailing_slash_is_the_same_route() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos/"))
+        .json(&json!({ "text": "slashed" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(res.headers()["location"], format!("/todos/{}", todo["id"]));
+
+    let mut res = TestClient::get(url(&format!("/todos/{}/", todo["id"]))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let fetched: Value = res.take_json().await.unwrap();
+    assert_eq!(fetched["text"], "slashed");
+
+    let mut res = TestClient::get(url("/todos/")).send(&service).await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(list.len(), 1);
+}
+
+#[tokio::test]
+async fn seed_requires_dev_mode() {
+    let (_guard, service) = setup().await;
+    create(&service, "will be replaced").await;
+
+    std::env::remove_var("DEV_MODE");
+    let res = TestClient::post(url("/todos/seed")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+    std::env::set_var("DEV_MODE", "true");
+    let mut res = TestClient::post(url("/todos/seed")).send(&service).await;
+    std::env::remove_var("DEV_MODE");
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    let seeded: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(seeded.len(), 5);
+    assert_eq!(seeded[0]["id"], 1);
+
+    let mut res = TestClient::get(url("/todos")).send(&service).await;
+    let list: Vec<Value> = res.take_json().await.unwrap();
+    assert_eq!(list, seeded);
+    assert_eq!(create(&service, "after seed").await["id"], 6);
+}
+
+#[tokio::test]
+async fn text_is_trimmed_before_storing() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "  buy  milk \n").await;
+    assert_eq!(todo["text"], "buy  milk");
+
+    // dedupe compara sin importar los espacios repetidos
+    let res = TestClient::post(url("/todos"))
+        .query("dedupe", true)
+        .json(&json!({ "text": "Buy milk" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+}
+
+#[tokio::test]
+async fn put_keeps_id_and_created_at() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "original").await;
+    let path = format!("/todos/{}", todo["id"]);
+
+    let mut res = TestClient::put(url(&path))
+        .json(&json!({
+            "id": 777,
+            "text": "replaced",
+            "completed": true,
+            "created_at": "2000-01-01T00:00:00Z",
+            "version": 1,
+        }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let replaced: Value = res.take_json().await.unwrap();
+    assert_eq!((replaced["id"].clone(), replaced["text"].clone()), (todo["id"].clone(), json!("replaced")));
+
+    let mut res = TestClient::get(url(&path)).send(&service).await;
+    let updated: Value = res.take_json().await.unwrap();
+    assert_eq!(updated["id"], todo["id"]);
+    assert_eq!(updated["created_at"], todo["created_at"]);
+    assert_eq!(updated["text"], "replaced");
+    assert_ne!(updated["updated_at"], todo["updated_at"]);
+
+    let res = TestClient::get(url("/todos/777")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn graphql_queries_and_mutates_the_same_store() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/graphql"))
+        .json(&json!({
+            "query": r#"mutation { createTodo(input: { text: "from graphql", priority: 2 }) { id text version } }"#
+        }))
+        .send(&service)
+        .await;
+    let body: Value = res.take_json().await.unwrap();
+    let created = &body["data"]["createTodo"];
+    assert_eq!(created["text"], "from graphql");
+    let id = created["id"].as_i64().unwrap();
+
+    // lo creado por GraphQL se ve desde REST
+    let mut res = TestClient::get(url(&format!("/todos/{id}"))).send(&service).await;
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["priority"], 2);
+
+    let mut res = TestClient::post(url("/graphql"))
+        .json(&json!({
+            "query": "mutation($id: Int!) { updateTodo(id: $id, version: 1, input: { completed: true }) { completed version } }",
+            "variables": { "id": id },
+        }))
+        .send(&service)
+        .await;
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body["data"]["updateTodo"], json!({ "completed": true, "version": 2 }));
+
+    let mut res = TestClient::post(url("/graphql"))
+        .json(&json!({ "query": "{ todos(filter: { completed: true }) { id } }" }))
+        .send(&service)
+        .await;
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body["data"]["todos"], json!([{ "id": id }]));
+
+    // una version vieja es un error con el mismo codigo que en REST
+    let mut res = TestClient::post(url("/graphql"))
+        .json(&json!({
+            "query": "mutation($id: Int!) { updateTodo(id: $id, version: 1, input: { text: \"stale\" }) { id } }",
+            "variables": { "id": id },
+        }))
+        .send(&service)
+        .await;
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body["errors"][0]["extensions"]["code"], 409);
+
+    let mut res = TestClient::post(url("/graphql"))
+        .json(&json!({
+            "query": "mutation($id: Int!) { deleteTodo(id: $id) }",
+            "variables": { "id": id },
+        }))
+        .send(&service)
+        .await;
+    let body: Value = res.take_json().await.unwrap();
+    assert_eq!(body["data"]["deleteTodo"], true);
+    let res = TestClient::get(url(&format!("/todos/{id}"))).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn get_is_cached_until_the_todo_changes() {
+    let (_guard, service) = setup().await;
+
+    let todo = create(&service, "cached read").await;
+    let path = format!("/todos/{}", todo["id"]);
+
+    let res = TestClient::get(url(&path)).send(&service).await;
+    assert_eq!(res.headers()["x-cache"], "MISS");
+    let res = TestClient::get(url(&path)).send(&service).await;
+    assert_eq!(res.headers()["x-cache"], "HIT");
+
+    TestClient::patch(url(&path))
+        .json(&json!({ "text": "fresh read", "version": 1 }))
+        .send(&service)
+        .await;
+    let mut res = TestClient::get(url(&path)).send(&service).await;
+    assert_eq!(res.headers()["x-cache"], "MISS");
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["text"], "fresh read");
+}
+
+#[tokio::test]
+async fn assignee_filters_todos() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "review the PR", "assignee": "  Alice " }))
+        .send(&service)
+        .await;
+    let alice: Value = res.take_json().await.unwrap();
+    assert_eq!(alice["assignee"], "alice");
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "deploy", "assignee": "bob" }))
+        .send(&service)
+        .await;
+    let bob: Value = res.take_json().await.unwrap();
+    let nobody = create(&service, "whoever gets to it").await;
+    assert!(nobody["assignee"].is_null());
+
+    let mut res = TestClient::get(url("/todos?assignee=ALICE")).send(&service).await;
+    assert_eq!(res.take_json::<Vec<Value>>().await.unwrap(), vec![alice]);
+    for query in ["assignee=", "unassigned=true"] {
+        let mut res = TestClient::get(url(&format!("/todos?{query}"))).send(&service).await;
+        assert_eq!(res.take_json::<Vec<Value>>().await.unwrap(), vec![nobody.clone()]);
+    }
+
+    // un assignee vacio en un PATCH lo desasigna
+    let path = format!("/todos/{}", bob["id"]);
+    let mut res = TestClient::patch(url(&path))
+        .json(&json!({ "version": bob["version"], "assignee": "" }))
+        .send(&service)
+        .await;
+    assert!(res.take_json::<Value>().await.unwrap()["assignee"].is_null());
+    let mut res = TestClient::get(url("/todos?unassigned=true")).send(&service).await;
+    assert_eq!(res.take_json::<Vec<Value>>().await.unwrap().len(), 2);
+
+    let res = TestClient::get(url("/todos?assignee=alice&unassigned=true")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+}
+
+// receptor de webhooks de prueba: contesta 200 a cada POST y manda el cuerpo JSON por el canal
+async fn webhook_receiver() -> (String, tokio::sync::mpsc::UnboundedReceiver<Value>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = Vec::new();
+            let mut chunk = [0; 4096];
+            let body = loop {
+                let read = stream.read(&mut chunk).await.unwrap();
+                request.extend_from_slice(&chunk[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length: usize = head
+                        .lines()
+                        .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                        .unwrap();
+                    if body.len() >= length {
+                        break body.to_string();
+                    }
+                }
+            };
+            stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.unwrap();
+            sender.send(serde_json::from_str(&body).unwrap()).unwrap();
+        }
+    });
+    (format!("http://{addr}/hooks/overdue"), receiver)
+}
+
+#[tokio::test]
+async fn overdue_webhook_notifies_each_todo_once() {
+    let (_guard, service) = setup().await;
+    let (webhook_url, mut received) = webhook_receiver().await;
+    let mut webhook = todo_api::webhook::OverdueWebhook::new(webhook_url.parse().unwrap(), 1);
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "late", "due_date": "2000-01-01T00:00:00Z" }))
+        .send(&service)
+        .await;
+    let late: Value = res.take_json().await.unwrap();
+    TestClient::post(url("/todos"))
+        .json(&json!({ "text": "not yet", "due_date": "2999-01-01T00:00:00Z" }))
+        .send(&service)
+        .await;
+
+    let list = || async {
+        let mut res = TestClient::get(url("/todos")).send(&service).await;
+        let todos: Vec<todo_api::models::Todo> = res.take_json().await.unwrap();
+        todos.into_iter().map(|todo| (None, todo)).collect::<Vec<_>>()
+    };
+    let todos = list().await;
+    // la primera pasada solo anota lo que ya estaba vencido: un reinicio no vuelve a avisarlo
+    let mut restarted = todo_api::webhook::OverdueWebhook::new(webhook_url.parse().unwrap(), 1);
+    assert!(restarted.notify(&todos, chrono::Utc::now()).await.is_empty());
+    assert!(restarted.notify(&todos, chrono::Utc::now()).await.is_empty());
+
+    // con la primera pasada antes de que venza, se avisa cuando vence
+    let before = "1999-01-01T00:00:00Z".parse().unwrap();
+    assert!(webhook.notify(&todos, before).await.is_empty());
+    assert_eq!(webhook.notify(&todos, chrono::Utc::now()).await, vec![late["id"].as_i64().unwrap()]);
+    let notification = received.recv().await.unwrap();
+    assert_eq!(notification["event"], "overdue");
+    assert_eq!(notification["todo"], late);
+
+    // el mismo id de otro usuario es otro todo
+    let other: Vec<_> = todos.iter().map(|(_, todo)| (Some("bob".to_string()), todo.clone())).collect();
+    assert_eq!(webhook.notify(&[todos.clone(), other].concat(), chrono::Utc::now()).await.len(), 1);
+    assert_eq!(received.recv().await.unwrap()["user"], "bob");
+
+    // la siguiente pasada no vuelve a avisar
+    assert!(webhook.notify(&todos, chrono::Utc::now()).await.is_empty());
+
+    // si le cambian la fecha y vuelve a vencer, se avisa de nuevo
+    let path = format!("/todos/{}", late["id"]);
+    TestClient::patch(url(&path))
+        .json(&json!({ "version": late["version"], "due_date": "2001-01-01T00:00:00Z" }))
+        .send(&service)
+        .await;
+    let todos = list().await;
+    assert_eq!(webhook.notify(&todos, chrono::Utc::now()).await.len(), 1);
+    assert_eq!(received.recv().await.unwrap()["todo"]["due_date"], "2001-01-01T00:00:00Z");
+}
+
+#[tokio::test]
+async fn identical_creates_within_the_dedup_window_are_replayed() {
+    let (_guard, service) = setup().await;
+
+    std::env::set_var("CREATE_DEDUP_WINDOW_SECS", "60");
+    let first = create(&service, "double click").await;
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "  double click " }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    assert_eq!(res.headers()["idempotent-replayed"], "true");
+    assert_eq!(res.take_json::<Value>().await.unwrap(), first);
+
+    // otro cuerpo, o una Idempotency-Key, es otro todo
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "double click", "priority": 2 }))
+        .send(&service)
+        .await;
+    assert_ne!(res.take_json::<Value>().await.unwrap()["id"], first["id"]);
+    let mut res = TestClient::post(url("/todos"))
+        .add_header("idempotency-key", "on-purpose", true)
+        .json(&json!({ "text": "double click" }))
+        .send(&service)
+        .await;
+    assert_ne!(res.take_json::<Value>().await.unwrap()["id"], first["id"]);
+    std::env::remove_var("CREATE_DEDUP_WINDOW_SECS");
+
+    // sin la variable no se deduplica
+    let again = create(&service, "double click").await;
+    assert_ne!(again["id"], first["id"]);
+}
+
+#[tokio::test]
+async fn bulk_tags_add_and_remove_across_todos() {
+    let (_guard, service) = setup().await;
+
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "report", "tags": ["home"] }))
+        .send(&service)
+        .await;
+    let report: Value = res.take_json().await.unwrap();
+    let mut res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "slides", "tags": ["work"] }))
+        .send(&service)
+        .await;
+    let slides: Value = res.take_json().await.unwrap();
+
+    let mut res = TestClient::patch(url("/todos/tags"))
+        .json(&json!({ "ids": [report["id"], slides["id"], report["id"], 999], "add": [" Work ", "q3"], "remove": ["HOME"] }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+    let result: Value = res.take_json().await.unwrap();
+    assert_eq!(result, json!({ "updated": [report["id"], slides["id"]], "unchanged": [], "missing": [999] }));
+
+    let path = format!("/todos/{}", report["id"]);
+    let mut res = TestClient::get(url(&path)).send(&service).await;
+    let todo: Value = res.take_json().await.unwrap();
+    assert_eq!(todo["tags"], json!(["q3", "work"]));
+    assert_eq!(todo["version"], report["version"].as_u64().unwrap() + 1);
+
+    // repetir la operacion no cambia nada
+    let mut res = TestClient::patch(url("/todos/tags"))
+        .json(&json!({ "ids": [report["id"]], "add": ["work"] }))
+        .send(&service)
+        .await;
+    let result: Value = res.take_json().await.unwrap();
+    assert_eq!(result["unchanged"], json!([report["id"]]));
+
+    // GET /todos/tags sigue listando los tags
+    let mut res = TestClient::get(url("/todos/tags")).send(&service).await;
+    assert_eq!(res.take_json::<Value>().await.unwrap(), json!(["q3", "work"]));
+
+    for body in [
+        json!({ "ids": [], "add": ["work"] }),
+        json!({ "ids": [report["id"]] }),
+        json!({ "ids": [report["id"]], "add": ["  "] }),
+        json!({ "ids": [report["id"]], "add": ["work"], "remove": ["Work"] }),
+    ] {
+        let res = TestClient::patch(url("/todos/tags")).json(&body).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST), "{body}");
+    }
+}
+
+#[tokio::test]
+async fn cors_allows_and_exposes_the_api_headers() {
+    let (_guard, service) = setup().await;
+
+    let res = TestClient::options(url("/todos"))
+        .add_header("origin", "https://app.example.com", true)
+        .add_header("access-control-request-method", "GET", true)
+        .add_header("access-control-request-headers", "if-modified-since, x-request-id", true)
+        .send(&service)
+        .await;
+    let allowed = res.headers()["access-control-allow-headers"].to_str().unwrap().to_lowercase();
+    assert!(allowed.contains("if-modified-since"));
+    assert!(allowed.contains("x-request-id"));
+
+    create(&service, "exposed").await;
+    let res = TestClient::get(url("/todos"))
+        .add_header("origin", "https://app.example.com", true)
+        .send(&service)
+        .await;
+    let exposed = res.headers()["access-control-expose-headers"].to_str().unwrap().to_lowercase();
+    for name in [
+        "x-total-count",
+        "location",
+        "x-request-id",
+        "x-limit-clamped",
+        "idempotent-replayed",
+        "retry-after",
+    ] {
+        assert!(exposed.contains(name), "{name} is not exposed: {exposed}");
+    }
+}
+
+#[tokio::test]
+async fn api_key_protects_writes_and_optionally_reads() {
+    let (_guard, _) = setup().await;
+
+    std::env::set_var("API_KEY", "secret");
+    let service = todo_api::service();
+    let post = |auth: Option<&'static str>| {
+        let mut req = TestClient::post(url("/todos")).json(&json!({ "text": "guarded" }));
+        if let Some(auth) = auth {
+            req = req.add_header("authorization", auth, true);
+        }
+        req
+    };
+    for auth in [None, Some("Bearer wrong"), Some("secret")] {
+        let mut res = post(auth).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::UNAUTHORIZED), "{auth:?}");
+        assert_eq!(res.headers()["www-authenticate"], "Bearer");
+        assert_eq!(res.take_json::<Value>().await.unwrap()["code"], 401);
+    }
+    let res = post(Some("Bearer secret")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::CREATED));
+    // por defecto las lecturas quedan abiertas
+    let res = TestClient::get(url("/todos")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+
+    std::env::set_var("API_KEY_PROTECT_READS", "true");
+    let service = todo_api::service();
+    std::env::remove_var("API_KEY_PROTECT_READS");
+    std::env::remove_var("API_KEY");
+    let res = TestClient::get(url("/todos")).send(&service).await;
+    assert_eq!(res.status_code, Some(StatusCode::UNAUTHORIZED));
+    let res = TestClient::get(url("/todos"))
+        .add_header("authorization", "Bearer secret", true)
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::OK));
+}
+
+#[tokio::test]
+async fn invalid_config_is_rejected_at_startup() {
+    let (_guard, _) = setup().await;
+
+    todo_api::check_config();
+    // MAX_TODOS se lee en cada request, pero un valor invalido igual frena el arranque
+    std::env::set_var("MAX_TODOS", "lots");
+    let result = std::panic::catch_unwind(todo_api::check_config);
+    std::env::remove_var("MAX_TODOS");
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains("invalid MAX_TODOS \"lots\""), "{message}");
+}
+
+#[tokio::test]
+async fn rejected_creates_do_not_spend_ids() {
+    let (_guard, service) = setup().await;
+
+    let first = create(&service, "first").await["id"].as_i64().unwrap();
+    let res = TestClient::post(url("/todos"))
+        .json(&json!({ "text": "orphan", "parent_id": 999999 }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    let res = TestClient::post(url("/todos"))
+        .query("dedupe", true)
+        .json(&json!({ "text": "first" }))
+        .send(&service)
+        .await;
+    assert_eq!(res.status_code, Some(StatusCode::CONFLICT));
+
+    // ni los rechazos ni un dry run gastan un id
+    let mut res = TestClient::post(url("/todos"))
+        .query("dry_run", true)
+        .json(&json!({ "text": "preview" }))
+        .send(&service)
+        .await;
+    let preview: Value = res.take_json().await.unwrap();
+    assert_eq!(preview["id"], first + 1);
+    assert_eq!(create(&service, "second").await["id"], first + 1);
+}